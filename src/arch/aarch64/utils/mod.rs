@@ -1,5 +1,5 @@
-mod bitmap;
-mod util;
-
-pub use util::*;
+mod bitmap;
+mod util;
+
+pub use util::*;
 pub use bitmap::*;
\ No newline at end of file
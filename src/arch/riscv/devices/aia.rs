@@ -0,0 +1,211 @@
+//! Virtual AIA: an IMSIC interrupt file for each vCPU, either a guest interrupt file of the host's
+//! IMSIC or an emulated one, and an APLIC in MSI delivery mode forwarding wired interrupts to
+//! them as MSIs.
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{MmioDevice, VirtualInterruptController};
+use crate::arch::has_aia;
+use crate::devices::aia::{AplicState, ImsicFile, APLIC_SIZE, IMSIC_PAGE_SIZE, SETEIPNUM_LE};
+use crate::{vcpus::MAX_CPUS, GuestPhysAddr, HostPhysAddr, HostVirtAddr, HyperError, HyperResult};
+
+/// The number of guest interrupt files of the host's IMSICs, GEILEN, the lowest of all harts.
+static GEILEN: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Detects GEILEN on this hart, by writing all ones to `hgeie` and reading back which bits stuck.
+/// Must be called on every hart before any VM is created.
+pub(crate) fn detect_geilen() {
+    let hgeie: usize;
+    unsafe {
+        core::arch::asm!(
+            "csrrw {old}, hgeie, {ones}",
+            "csrr {hgeie}, hgeie",
+            "csrw hgeie, {old}",
+            old = out(reg) _,
+            ones = in(reg) usize::MAX,
+            hgeie = out(reg) hgeie,
+        );
+    }
+    GEILEN.fetch_min(hgeie.count_ones() as usize, Ordering::Relaxed);
+}
+
+/// Returns the number of guest interrupt files of each host IMSIC, 0 without AIA.
+pub fn num_guest_interrupt_files() -> usize {
+    match GEILEN.load(Ordering::Relaxed) {
+        _ if !has_aia() => 0,
+        usize::MAX => 0,
+        geilen => geilen,
+    }
+}
+
+/// A guest interrupt file of a host IMSIC, given to a vCPU as its interrupt file. The guest then
+/// accesses it directly, without exits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GuestInterruptFile {
+    /// The hart whose IMSIC holds the file. The vCPU must run on that hart.
+    pub hart_id: usize,
+    /// The number of the file, from 1 to GEILEN, as selected by `hstatus.VGEIN`.
+    pub guest_index: usize,
+    /// Host physical address of the file's MSI page, mapped into the guest.
+    pub host_paddr: HostPhysAddr,
+    /// Host virtual address the file's MSI page is mapped at in the hypervisor, where the
+    /// emulated APLIC writes its MSIs.
+    pub host_vaddr: HostVirtAddr,
+}
+
+/// Configuration of a virtual AIA: a supervisor-level APLIC in MSI delivery mode, and an IMSIC
+/// interrupt file for each vCPU.
+#[derive(Clone, Debug)]
+pub struct AiaConfig {
+    /// Guest physical base address of the APLIC.
+    pub aplic_base: GuestPhysAddr,
+    /// Number of APLIC interrupt sources, including the reserved source 0.
+    pub num_sources: usize,
+    /// Guest physical address of the MSI page of vCPU 0's interrupt file. Those of the other
+    /// vCPUs follow it, `IMSIC_PAGE_SIZE` apart.
+    pub imsic_base: GuestPhysAddr,
+    /// Number of interrupt identities of the emulated interrupt files, including the reserved
+    /// identity 0.
+    pub num_ids: usize,
+    /// The guest interrupt files backing the interrupt files of the vCPUs, indexed by vCPU id.
+    /// The interrupt files of the vCPUs without one are emulated.
+    pub guest_files: Vec<Option<GuestInterruptFile>>,
+    /// The interrupt identities of the host's supervisor interrupt file passed through to the
+    /// guest, with the APLIC source each of them raises.
+    pub host_irqs: Vec<(usize, usize)>,
+}
+
+impl Default for AiaConfig {
+    /// The supervisor-level APLIC and IMSICs of the QEMU virt machine with `aia=aplic-imsic`,
+    /// with emulated interrupt files.
+    fn default() -> Self {
+        Self {
+            aplic_base: 0xd00_0000,
+            num_sources: 96,
+            imsic_base: 0x2800_0000,
+            num_ids: 256,
+            guest_files: Vec::new(),
+            host_irqs: Vec::new(),
+        }
+    }
+}
+
+/// A virtual AIA, delivering the MSIs of an emulated APLIC to the guest interrupt files or the
+/// emulated interrupt files of the vCPUs.
+pub struct VirtAia {
+    aplic: AplicState,
+    // The emulated interrupt files, `None` for the vCPUs with a guest interrupt file.
+    files: Vec<Option<ImsicFile>>,
+    config: AiaConfig,
+}
+
+impl VirtAia {
+    /// Creates a virtual AIA with the given configuration.
+    pub fn new(config: AiaConfig) -> Self {
+        Self {
+            aplic: AplicState::new(config.num_sources),
+            files: Self::emulated_files(&config),
+            config,
+        }
+    }
+
+    fn emulated_files(config: &AiaConfig) -> Vec<Option<ImsicFile>> {
+        (0..MAX_CPUS)
+            .map(|vcpu_id| match config.guest_files.get(vcpu_id) {
+                Some(Some(_)) => None,
+                _ => Some(ImsicFile::new(config.num_ids)),
+            })
+            .collect()
+    }
+
+    /// Sends the MSIs of the APLIC to the interrupt files of the vCPUs.
+    fn deliver_msis(&mut self) {
+        for msi in self.aplic.take_msis() {
+            if let Some(Some(file)) = self.config.guest_files.get(msi.hart) {
+                // Safety: the guest interrupt file's MSI page is mapped at `host_vaddr`.
+                unsafe {
+                    core::ptr::write_volatile(
+                        (file.host_vaddr + SETEIPNUM_LE) as *mut u32,
+                        msi.eiid as u32,
+                    )
+                };
+            } else if let Some(Some(file)) = self.files.get_mut(msi.hart) {
+                file.set_pending(msi.eiid);
+            }
+        }
+    }
+}
+
+impl MmioDevice for VirtAia {
+    fn read(&mut self, offset: usize, width: usize) -> HyperResult<usize> {
+        if width != 4 {
+            return Err(HyperError::InvalidParam);
+        }
+        Ok(self.aplic.read_u32(offset) as usize)
+    }
+
+    fn write(&mut self, offset: usize, width: usize, val: usize) -> HyperResult<()> {
+        if width != 4 {
+            return Err(HyperError::InvalidParam);
+        }
+        self.aplic.write_u32(offset, val as u32);
+        self.deliver_msis();
+        Ok(())
+    }
+}
+
+impl VirtualInterruptController for VirtAia {
+    fn base(&self) -> GuestPhysAddr {
+        self.config.aplic_base
+    }
+
+    fn size(&self) -> usize {
+        APLIC_SIZE
+    }
+
+    fn handle_host_irq(&mut self) {
+        loop {
+            // Claim the top interrupt of the host's supervisor interrupt file from `stopei`.
+            let topei: usize;
+            unsafe { core::arch::asm!("csrrw {}, 0x15c, zero", out(reg) topei) };
+            let id = topei >> 16;
+            if id == 0 {
+                break;
+            }
+            match self
+                .config
+                .host_irqs
+                .iter()
+                .find(|&&(host_id, _)| host_id == id)
+            {
+                Some(&(_, source)) => self.aplic.trigger(source),
+                None => warn!("Host interrupt {} isn't passed through", id),
+            }
+        }
+        self.deliver_msis();
+    }
+
+    fn has_interrupt(&self, vcpu_id: usize) -> bool {
+        // Guest interrupt files assert the vCPU's external interrupt themselves.
+        matches!(self.files.get(vcpu_id), Some(Some(file)) if file.has_interrupt())
+    }
+
+    fn reset(&mut self) {
+        // The guest interrupt files keep their state, the guest initializes them again as it
+        // boots.
+        self.aplic = AplicState::new(self.config.num_sources);
+        self.files = Self::emulated_files(&self.config);
+    }
+
+    fn msi_page(&self, vcpu_id: usize) -> Option<GuestPhysAddr> {
+        (vcpu_id < MAX_CPUS).then(|| self.config.imsic_base + vcpu_id * IMSIC_PAGE_SIZE)
+    }
+
+    fn guest_interrupt_file(&self, vcpu_id: usize) -> Option<GuestInterruptFile> {
+        self.config.guest_files.get(vcpu_id).copied().flatten()
+    }
+
+    fn interrupt_file(&mut self, vcpu_id: usize) -> Option<&mut ImsicFile> {
+        self.files.get_mut(vcpu_id).and_then(Option::as_mut)
+    }
+}
@@ -0,0 +1,35 @@
+/// The console of a VM, fed by the SBI Debug Console and legacy console calls of its guest.
+/// Giving each VM its own sink keeps the output of several guests apart.
+pub trait ConsoleSink {
+    /// Outputs `bytes` written by the guest.
+    fn write(&mut self, bytes: &[u8]);
+
+    /// Reads pending input for the guest into `buf` without blocking, returning the number of
+    /// bytes read.
+    fn read(&mut self, buf: &mut [u8]) -> usize;
+}
+
+/// A console sink forwarding to the host's SBI console.
+#[derive(Default)]
+pub struct HostConsole;
+
+impl ConsoleSink for HostConsole {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            sbi_rt::legacy::console_putchar(byte as usize);
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        for byte in buf.iter_mut() {
+            // The legacy getchar returns -1 if there is no input.
+            match sbi_rt::legacy::console_getchar() {
+                usize::MAX => break,
+                c => *byte = c as u8,
+            }
+            count += 1;
+        }
+        count
+    }
+}
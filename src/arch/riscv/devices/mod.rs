@@ -1 +1,45 @@
-pub mod plic;
+pub mod aia;
+pub mod console;
+pub mod plic;
+
+pub use crate::devices::{ImsicFile, MmioAccess, MmioBus, MmioDevice};
+use crate::GuestPhysAddr;
+pub use aia::GuestInterruptFile;
+pub use console::{ConsoleSink, HostConsole};
+
+/// A virtual interrupt controller that delivers external interrupts to the vCPUs of a VM, e.g. a
+/// PLIC or an APLIC/IMSIC model. Its registers are accessed through `MmioDevice`.
+pub trait VirtualInterruptController: MmioDevice {
+    /// Returns the guest physical base address of the controller's registers.
+    fn base(&self) -> GuestPhysAddr;
+
+    /// Returns the size of the controller's register space.
+    fn size(&self) -> usize;
+
+    /// Handles an external interrupt taken by the host while the guest was running.
+    fn handle_host_irq(&mut self);
+
+    /// Returns whether the virtual external interrupt of vCPU `vcpu_id` is asserted.
+    fn has_interrupt(&self, vcpu_id: usize) -> bool;
+
+    /// Brings the controller back to its power-on state, for a system reset of the VM.
+    fn reset(&mut self);
+
+    /// Returns the guest physical address of the MSI page of vCPU `vcpu_id`'s IMSIC interrupt
+    /// file, for the controllers giving the vCPUs one.
+    fn msi_page(&self, _vcpu_id: usize) -> Option<GuestPhysAddr> {
+        None
+    }
+
+    /// Returns the guest interrupt file of the host's IMSIC backing vCPU `vcpu_id`'s interrupt
+    /// file, if it isn't emulated. Its MSI page is mapped into the guest, and the guest accesses
+    /// it directly.
+    fn guest_interrupt_file(&self, _vcpu_id: usize) -> Option<GuestInterruptFile> {
+        None
+    }
+
+    /// Returns vCPU `vcpu_id`'s emulated interrupt file, if it has one.
+    fn interrupt_file(&mut self, _vcpu_id: usize) -> Option<&mut ImsicFile> {
+        None
+    }
+}
@@ -1,77 +1,167 @@
-use crate::{
-    arch::csrs::{traps, RiscvCsrTrait, CSR},
-    vcpus::MAX_CPUS,
-};
-
-/// Number of contexts for the PLIC. Value is twice the max number of harts because each hart will
-/// have one M-mode context and one S-mode context.
-pub const MAX_CONTEXTS: usize = 2 * MAX_CPUS;
-
-pub struct PlicState {
-    base: usize,
-    source_priority: [u32; 512],
-    pending: [u32; 16],
-    enable: [[u32; 32]; MAX_CONTEXTS],
-    thresholds: [u32; MAX_CONTEXTS],
-    pub claim_complete: [u32; MAX_CONTEXTS],
-}
-
-impl PlicState {
-    pub fn new(base: usize) -> Self {
-        Self {
-            base,
-            source_priority: [0; 512],
-            pending: [0; 16],
-            enable: [[0; 32]; MAX_CONTEXTS],
-            thresholds: [0; MAX_CONTEXTS],
-            claim_complete: [0; MAX_CONTEXTS],
-        }
-    }
-
-    pub fn base(&self) -> usize {
-        self.base
-    }
-
-    pub fn read_u32(&mut self, addr: usize) -> u32 {
-        let offset = addr.wrapping_sub(self.base);
-        if (0x20_0000..0x20_0000 + 0x1000 * MAX_CONTEXTS).contains(&offset) {
-            // threshold/claim/complete
-            let hart = (offset - 0x200000) / 0x1000;
-            let index = ((offset - 0x200000) & 0xfff) >> 2;
-            if index == 1 {
-                // debug!("PLIC read@{:#x} -> {:#x}", addr, self.claim_complete[hart]);
-                return self.claim_complete[hart];
-            }
-            todo!()
-        }
-        todo!()
-    }
-
-    pub fn write_u32(&mut self, addr: usize, val: u32) {
-        // debug!("PLIC write@{:#x} -> {:#x}", addr, val);
-        let offset = addr.wrapping_sub(self.base);
-        // threshold/claim/complete
-        if (0x200000..0x200000 + 0x1000 * MAX_CONTEXTS).contains(&offset) {
-            let hart = (offset - 0x200000) / 0x1000;
-            let index = ((offset - 0x200000) & 0xfff) >> 2;
-            if index == 0 {
-                // threshold
-                self.thresholds[hart] = val;
-                unsafe {
-                    core::ptr::write_volatile(addr as *mut u32, val);
-                }
-            } else if index == 1 {
-                // claim
-                unsafe {
-                    core::ptr::write_volatile(addr as *mut u32, val);
-                }
-                self.claim_complete[hart] = 0;
-                // Send Interrupt to the hart
-                CSR.hvip
-                    .read_and_clear_bits(traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL);
-            }
-        } else {
-            todo!()
-        }
-    }
-}
+use alloc::vec::Vec;
+
+use super::{MmioDevice, VirtualInterruptController};
+use crate::devices::plic::{
+    context_hart, supervisor_context, PlicRegister, PlicSources, PlicState, MAX_CONTEXTS,
+    PLIC_SIZE, SOURCE_WORDS,
+};
+use crate::{GuestPhysAddr, HostPhysAddr, HyperError, HyperResult};
+
+/// Configuration of a virtual PLIC whose interrupts are passed through from the host PLIC.
+#[derive(Clone, Debug)]
+pub struct PlicConfig {
+    /// Guest physical base address of the PLIC.
+    pub base: GuestPhysAddr,
+    /// Size of the PLIC register space.
+    pub size: usize,
+    /// Number of interrupt sources, including the reserved source 0.
+    pub num_sources: usize,
+    /// Physical base address of the host PLIC.
+    pub host_base: HostPhysAddr,
+    /// The host PLIC context that receives the interrupts passed through to the guest.
+    pub host_context: usize,
+    /// The sources of the host PLIC passed through to the guest. The guest's priority and enable
+    /// writes only reach the host PLIC for them, the host keeps its own settings of the others.
+    pub passthrough_irqs: Vec<u32>,
+}
+
+impl Default for PlicConfig {
+    /// The PLIC of the QEMU virt machine, passing through all its sources on the S-mode context
+    /// of hart 0.
+    fn default() -> Self {
+        Self {
+            base: 0xC00_0000,
+            size: PLIC_SIZE,
+            num_sources: 96,
+            host_base: 0xC00_0000,
+            host_context: supervisor_context(0),
+            passthrough_irqs: (1..96).collect(),
+        }
+    }
+}
+
+/// A virtual PLIC backed by `PlicState`, passing through the host PLIC's interrupts.
+pub struct VirtPlic {
+    state: PlicState,
+    passthrough: PlicSources,
+    config: PlicConfig,
+}
+
+impl VirtPlic {
+    /// Creates a virtual PLIC with the given configuration.
+    pub fn new(config: PlicConfig) -> Self {
+        Self {
+            state: PlicState::new(config.base, config.num_sources),
+            passthrough: config.passthrough_irqs.iter().copied().collect(),
+            config,
+        }
+    }
+
+    fn host_reg(&self, reg: PlicRegister) -> *mut u32 {
+        (self.config.host_base + reg.offset()) as *mut u32
+    }
+
+    /// Sets the enable bits of the passed through sources among `32 * word..32 * word + 32` on
+    /// the host context to `enabled`, keeping the host's own bits.
+    fn write_host_enable(&self, word: usize, enabled: u32) {
+        let enable = self.host_reg(PlicRegister::Enable {
+            context: self.config.host_context,
+            word,
+        });
+        // Safety: the host PLIC is mapped at `host_base`.
+        unsafe {
+            let current = core::ptr::read_volatile(enable);
+            core::ptr::write_volatile(
+                enable,
+                self.passthrough.merge_enable_word(word, current, enabled),
+            );
+        }
+    }
+}
+
+impl MmioDevice for VirtPlic {
+    fn read(&mut self, offset: usize, width: usize) -> HyperResult<usize> {
+        if width != 4 {
+            return Err(HyperError::InvalidParam);
+        }
+        Ok(self.state.read_u32(self.config.base + offset) as usize)
+    }
+
+    fn write(&mut self, offset: usize, width: usize, val: usize) -> HyperResult<()> {
+        if width != 4 {
+            return Err(HyperError::InvalidParam);
+        }
+        let val = val as u32;
+        let completed = self.state.write_u32(self.config.base + offset, val);
+        // Keep the host's source configuration in sync so that it receives the interrupts the
+        // guest is interested in, and complete the sources on the host context they were claimed
+        // from.
+        let host_write = match PlicRegister::from_offset(offset) {
+            Some(reg @ PlicRegister::Priority(source))
+                if self.passthrough.contains(source as u32) =>
+            {
+                Some((reg, val))
+            }
+            Some(PlicRegister::Enable { word, .. }) => {
+                let enabled = (0..MAX_CONTEXTS)
+                    .filter(|&context| context_hart(context).is_some())
+                    .fold(0, |acc, context| {
+                        acc | self.state.enable_word(context, word)
+                    });
+                self.write_host_enable(word, enabled);
+                None
+            }
+            Some(PlicRegister::ClaimComplete(_)) => {
+                completed.map(|irq| (PlicRegister::ClaimComplete(self.config.host_context), irq))
+            }
+            _ => None,
+        };
+        if let Some((reg, val)) = host_write {
+            // Safety: the host PLIC is mapped at `host_base`.
+            unsafe { core::ptr::write_volatile(self.host_reg(reg), val) };
+        }
+        Ok(())
+    }
+}
+
+impl VirtualInterruptController for VirtPlic {
+    fn base(&self) -> GuestPhysAddr {
+        self.config.base
+    }
+
+    fn size(&self) -> usize {
+        self.config.size
+    }
+
+    fn handle_host_irq(&mut self) {
+        let claim = self.host_reg(PlicRegister::ClaimComplete(self.config.host_context));
+        // Safety: the host PLIC is mapped at `host_base`.
+        let irq = unsafe { core::ptr::read_volatile(claim) };
+        // Zero if someone else claimed it already.
+        if irq != 0 {
+            self.state.set_pending(irq);
+        }
+    }
+
+    fn has_interrupt(&self, vcpu_id: usize) -> bool {
+        self.state.has_interrupt(supervisor_context(vcpu_id))
+    }
+
+    fn reset(&mut self) {
+        // The sources the guest didn't complete yet are still claimed on the host. Complete them
+        // there and stop forwarding the passed through sources until the guest enables them
+        // again.
+        let claim = self.host_reg(PlicRegister::ClaimComplete(self.config.host_context));
+        for word in 0..SOURCE_WORDS {
+            let mut claimed = self.state.unfinished_word(word);
+            while claimed != 0 {
+                let irq = word as u32 * 32 + claimed.trailing_zeros();
+                // Safety: the host PLIC is mapped at `host_base`.
+                unsafe { core::ptr::write_volatile(claim, irq) };
+                claimed &= claimed - 1;
+            }
+            self.write_host_enable(word, 0);
+        }
+        self.state = PlicState::new(self.config.base, self.config.num_sources);
+    }
+}
@@ -1,14 +1,125 @@
-use page_table::{PageTable64, PagingMetaData};
-use page_table_entry::riscv::Rv64PTE;
-
-pub struct Sv39GuestMetaData;
-
-impl PagingMetaData for Sv39GuestMetaData {
-    const LEVELS: usize = 3;
-    const PA_MAX_BITS: usize = 56;
-    // G-stage root page table: 16KiB
-    const VA_MAX_BITS: usize = 41;
-}
-
-/// Nested page table define.
-pub type NestedPageTable<I> = PageTable64<Sv39GuestMetaData, Rv64PTE, I>;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use page_table::{PageTable64, PagingIf, PagingMetaData};
+use page_table_entry::riscv::Rv64PTE;
+
+/// The shift of the MODE field of `hgatp`.
+const HGATP_MODE_SHIFT: usize = 60;
+/// The PPN field of `hgatp`.
+const HGATP_PPN_MASK: usize = (1 << 44) - 1;
+
+pub struct Sv39GuestMetaData;
+
+impl PagingMetaData for Sv39GuestMetaData {
+    const LEVELS: usize = 3;
+    const PA_MAX_BITS: usize = 56;
+    // G-stage root page table: 16KiB
+    const VA_MAX_BITS: usize = 41;
+}
+
+pub struct Sv48GuestMetaData;
+
+impl PagingMetaData for Sv48GuestMetaData {
+    const LEVELS: usize = 4;
+    const PA_MAX_BITS: usize = 56;
+    // G-stage root page table: 16KiB
+    const VA_MAX_BITS: usize = 50;
+}
+
+pub struct Sv57GuestMetaData;
+
+impl PagingMetaData for Sv57GuestMetaData {
+    const LEVELS: usize = 5;
+    const PA_MAX_BITS: usize = 56;
+    // G-stage root page table: 16KiB
+    const VA_MAX_BITS: usize = 59;
+}
+
+/// Nested page table define.
+pub type NestedPageTable<I> = PageTable64<Sv39GuestMetaData, Rv64PTE, I>;
+/// Nested page table for the Sv48x4 G-stage mode.
+pub type NestedPageTableSv48<I> = PageTable64<Sv48GuestMetaData, Rv64PTE, I>;
+/// Nested page table for the Sv57x4 G-stage mode.
+pub type NestedPageTableSv57<I> = PageTable64<Sv57GuestMetaData, Rv64PTE, I>;
+
+/// A guest page table built for one of the G-stage translation modes. A VM translates in the mode
+/// of its guest page table, so the two can't disagree.
+pub trait GStagePageTable {
+    /// The mode the page table is walked in.
+    const GSTAGE_MODE: GStageMode;
+}
+
+impl<I: PagingIf> GStagePageTable for NestedPageTable<I> {
+    const GSTAGE_MODE: GStageMode = GStageMode::Sv39x4;
+}
+
+impl<I: PagingIf> GStagePageTable for NestedPageTableSv48<I> {
+    const GSTAGE_MODE: GStageMode = GStageMode::Sv48x4;
+}
+
+impl<I: PagingIf> GStagePageTable for NestedPageTableSv57<I> {
+    const GSTAGE_MODE: GStageMode = GStageMode::Sv57x4;
+}
+
+/// The translation mode of the G-stage, as set in `hgatp.MODE`. The guest physical address space
+/// is 2 bits wider than the virtual one of the corresponding mode, and the root page table spans
+/// 16 KiB, aligned to 16 KiB, as allocated by `HyperCraftHal::alloc_16_page`.
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GStageMode {
+    /// 41-bit guest physical addresses, translated by [`NestedPageTable`].
+    Sv39x4 = 8,
+    /// 50-bit guest physical addresses, translated by [`NestedPageTableSv48`].
+    Sv48x4 = 9,
+    /// 59-bit guest physical addresses, translated by [`NestedPageTableSv57`].
+    Sv57x4 = 10,
+}
+
+/// The G-stage modes supported by all harts, as a bitmap of `hgatp.MODE` values.
+static SUPPORTED_MODES: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+impl GStageMode {
+    /// Returns the width of the guest physical addresses translated in this mode.
+    pub fn gpa_bits(self) -> usize {
+        match self {
+            Self::Sv39x4 => 41,
+            Self::Sv48x4 => 50,
+            Self::Sv57x4 => 59,
+        }
+    }
+
+    /// Returns whether all harts support this mode.
+    pub fn is_supported(self) -> bool {
+        SUPPORTED_MODES.load(Ordering::Relaxed) & (1 << self as usize) != 0
+    }
+
+    /// Returns the `hgatp` value, without VMID, to translate in this mode with the root page
+    /// table of the guest page table `token`.
+    pub(crate) fn hgatp(self, token: usize) -> usize {
+        (self as usize) << HGATP_MODE_SHIFT | (token & HGATP_PPN_MASK)
+    }
+}
+
+/// Detects the G-stage modes supported on this hart. A write of an unsupported mode to `hgatp`
+/// has no effect, so each mode is supported if it reads back. Must be called on every hart before
+/// any VM is created.
+pub(crate) fn detect_gstage_modes() {
+    let mut supported = 0;
+    for mode in [GStageMode::Sv39x4, GStageMode::Sv48x4, GStageMode::Sv57x4] {
+        let hgatp: usize;
+        unsafe {
+            core::arch::asm!(
+                "csrrw {old}, hgatp, {probe}",
+                "csrr {hgatp}, hgatp",
+                "csrw hgatp, {old}",
+                old = out(reg) _,
+                probe = in(reg) (mode as usize) << HGATP_MODE_SHIFT,
+                hgatp = out(reg) hgatp,
+            );
+        }
+        if hgatp >> HGATP_MODE_SHIFT == mode as usize {
+            supported |= 1 << mode as usize;
+        }
+    }
+    SUPPORTED_MODES.fetch_and(supported, Ordering::Relaxed);
+}
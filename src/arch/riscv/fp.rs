@@ -0,0 +1,234 @@
+//! Floating-point and vector state of the guests. The guest's `sstatus.FS` and `sstatus.VS`
+//! track whether it changed the registers since they were last saved, so they are only saved
+//! when dirty. The hypervisor itself must not use the FP or vector registers.
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The `sstatus.FS` field.
+pub const SSTATUS_FS: usize = 0b11 << 13;
+/// `sstatus.FS` set to Initial.
+pub const SSTATUS_FS_INITIAL: usize = 0b01 << 13;
+/// `sstatus.FS` set to Clean.
+pub const SSTATUS_FS_CLEAN: usize = 0b10 << 13;
+/// `sstatus.FS` set to Dirty.
+pub const SSTATUS_FS_DIRTY: usize = 0b11 << 13;
+/// The `sstatus.VS` field.
+pub const SSTATUS_VS: usize = 0b11 << 9;
+/// `sstatus.VS` set to Initial.
+pub const SSTATUS_VS_INITIAL: usize = 0b01 << 9;
+/// `sstatus.VS` set to Clean.
+pub const SSTATUS_VS_CLEAN: usize = 0b10 << 9;
+/// `sstatus.VS` set to Dirty.
+pub const SSTATUS_VS_DIRTY: usize = 0b11 << 9;
+
+/// The size of a vector register in bytes, 0 if the harts don't implement the V extension, or
+/// `usize::MAX` until a hart is set up.
+static VLENB: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Records the vector register size `vlenb` detected on this hart, `None` without the V
+/// extension. The vector state of a vCPU moves across harts with it, so guests only get one if
+/// all harts have the same `vlenb`. Must be called on every hart before any VM is created.
+pub(crate) fn detect_vlenb(vlenb: Option<usize>) {
+    let vlenb = vlenb.unwrap_or(0);
+    let _ = VLENB.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+        Some(if old == usize::MAX || old == vlenb {
+            vlenb
+        } else {
+            0
+        })
+    });
+}
+
+/// Returns the size of a vector register in bytes, 0 if guests don't get vector state.
+fn vlenb() -> usize {
+    match VLENB.load(Ordering::Relaxed) {
+        usize::MAX => 0,
+        vlenb => vlenb,
+    }
+}
+
+/// Returns whether the harts implement the V extension.
+pub(crate) fn has_vector() -> bool {
+    vlenb() != 0
+}
+
+/// The F/D register file and `fcsr` of a vCPU.
+#[derive(Default)]
+#[repr(C)]
+pub struct GuestFpState {
+    fregs: [u64; 32],
+    fcsr: usize,
+}
+
+impl GuestFpState {
+    /// Saves the floating-point registers of this hart.
+    pub fn save(&mut self) {
+        with_fp_enabled(|| unsafe {
+            asm!(
+                "fsd f0, 0*8({fregs})",
+                "fsd f1, 1*8({fregs})",
+                "fsd f2, 2*8({fregs})",
+                "fsd f3, 3*8({fregs})",
+                "fsd f4, 4*8({fregs})",
+                "fsd f5, 5*8({fregs})",
+                "fsd f6, 6*8({fregs})",
+                "fsd f7, 7*8({fregs})",
+                "fsd f8, 8*8({fregs})",
+                "fsd f9, 9*8({fregs})",
+                "fsd f10, 10*8({fregs})",
+                "fsd f11, 11*8({fregs})",
+                "fsd f12, 12*8({fregs})",
+                "fsd f13, 13*8({fregs})",
+                "fsd f14, 14*8({fregs})",
+                "fsd f15, 15*8({fregs})",
+                "fsd f16, 16*8({fregs})",
+                "fsd f17, 17*8({fregs})",
+                "fsd f18, 18*8({fregs})",
+                "fsd f19, 19*8({fregs})",
+                "fsd f20, 20*8({fregs})",
+                "fsd f21, 21*8({fregs})",
+                "fsd f22, 22*8({fregs})",
+                "fsd f23, 23*8({fregs})",
+                "fsd f24, 24*8({fregs})",
+                "fsd f25, 25*8({fregs})",
+                "fsd f26, 26*8({fregs})",
+                "fsd f27, 27*8({fregs})",
+                "fsd f28, 28*8({fregs})",
+                "fsd f29, 29*8({fregs})",
+                "fsd f30, 30*8({fregs})",
+                "fsd f31, 31*8({fregs})",
+                "frcsr {fcsr}",
+                fregs = in(reg) self.fregs.as_mut_ptr(),
+                fcsr = out(reg) self.fcsr,
+            );
+        });
+    }
+
+    /// Loads the saved floating-point registers into this hart.
+    pub fn restore(&self) {
+        with_fp_enabled(|| unsafe {
+            asm!(
+                "fld f0, 0*8({fregs})",
+                "fld f1, 1*8({fregs})",
+                "fld f2, 2*8({fregs})",
+                "fld f3, 3*8({fregs})",
+                "fld f4, 4*8({fregs})",
+                "fld f5, 5*8({fregs})",
+                "fld f6, 6*8({fregs})",
+                "fld f7, 7*8({fregs})",
+                "fld f8, 8*8({fregs})",
+                "fld f9, 9*8({fregs})",
+                "fld f10, 10*8({fregs})",
+                "fld f11, 11*8({fregs})",
+                "fld f12, 12*8({fregs})",
+                "fld f13, 13*8({fregs})",
+                "fld f14, 14*8({fregs})",
+                "fld f15, 15*8({fregs})",
+                "fld f16, 16*8({fregs})",
+                "fld f17, 17*8({fregs})",
+                "fld f18, 18*8({fregs})",
+                "fld f19, 19*8({fregs})",
+                "fld f20, 20*8({fregs})",
+                "fld f21, 21*8({fregs})",
+                "fld f22, 22*8({fregs})",
+                "fld f23, 23*8({fregs})",
+                "fld f24, 24*8({fregs})",
+                "fld f25, 25*8({fregs})",
+                "fld f26, 26*8({fregs})",
+                "fld f27, 27*8({fregs})",
+                "fld f28, 28*8({fregs})",
+                "fld f29, 29*8({fregs})",
+                "fld f30, 30*8({fregs})",
+                "fld f31, 31*8({fregs})",
+                "fscsr {fcsr}",
+                fregs = in(reg) self.fregs.as_ptr(),
+                fcsr = in(reg) self.fcsr,
+            );
+        });
+    }
+}
+
+/// The vector registers and vector CSRs of a vCPU.
+#[derive(Default)]
+pub struct GuestVectorState {
+    // v0-v31, allocated the first time they are saved.
+    vregs: Vec<u8>,
+    vstart: usize,
+    vcsr: usize,
+    vl: usize,
+    vtype: usize,
+}
+
+impl GuestVectorState {
+    /// Saves the vector registers of this hart.
+    pub fn save(&mut self) {
+        let vlenb = vlenb();
+        self.vregs.resize(32 * vlenb, 0);
+        // The whole-register stores (`vs8r.v`) are encoded by hand so that the assembler doesn't
+        // need to know about the V extension, like the vector CSRs are accessed by number.
+        with_vector_enabled(|| unsafe {
+            asm!(
+                "csrr {vstart}, 0x008",
+                "csrr {vcsr}, 0x00f",
+                "csrr {vl}, 0xc20",
+                "csrr {vtype}, 0xc21",
+                ".word 0xe2850027", // vs8r.v v0, (a0)
+                "add a0, a0, {stride}",
+                ".word 0xe2850427", // vs8r.v v8, (a0)
+                "add a0, a0, {stride}",
+                ".word 0xe2850827", // vs8r.v v16, (a0)
+                "add a0, a0, {stride}",
+                ".word 0xe2850c27", // vs8r.v v24, (a0)
+                stride = in(reg) 8 * vlenb,
+                vstart = out(reg) self.vstart,
+                vcsr = out(reg) self.vcsr,
+                vl = out(reg) self.vl,
+                vtype = out(reg) self.vtype,
+                inout("a0") self.vregs.as_mut_ptr() => _,
+            );
+        });
+    }
+
+    /// Loads the saved vector registers into this hart, or clears them if they were never saved.
+    pub fn restore(&mut self) {
+        let vlenb = vlenb();
+        self.vregs.resize(32 * vlenb, 0);
+        with_vector_enabled(|| unsafe {
+            asm!(
+                ".word 0xe2850007", // vl8re8.v v0, (a0)
+                "add a0, a0, {stride}",
+                ".word 0xe2850407", // vl8re8.v v8, (a0)
+                "add a0, a0, {stride}",
+                ".word 0xe2850807", // vl8re8.v v16, (a0)
+                "add a0, a0, {stride}",
+                ".word 0xe2850c07", // vl8re8.v v24, (a0)
+                ".word 0x80c5f057", // vsetvl zero, a1, a2
+                "csrw 0x008, {vstart}",
+                "csrw 0x00f, {vcsr}",
+                stride = in(reg) 8 * vlenb,
+                vstart = in(reg) self.vstart,
+                vcsr = in(reg) self.vcsr,
+                inout("a0") self.vregs.as_ptr() => _,
+                in("a1") self.vl,
+                in("a2") self.vtype,
+            );
+        });
+    }
+}
+
+/// Runs `f` with the FP unit of this hart enabled, whatever the host's `sstatus.FS` is.
+fn with_fp_enabled(f: impl FnOnce()) {
+    let sstatus: usize;
+    unsafe { asm!("csrrs {}, sstatus, {}", out(reg) sstatus, in(reg) SSTATUS_FS_INITIAL) };
+    f();
+    unsafe { asm!("csrw sstatus, {}", in(reg) sstatus) };
+}
+
+/// Runs `f` with the vector unit of this hart enabled, whatever the host's `sstatus.VS` is.
+fn with_vector_enabled(f: impl FnOnce()) {
+    let sstatus: usize;
+    unsafe { asm!("csrrs {}, sstatus, {}", out(reg) sstatus, in(reg) SSTATUS_VS_INITIAL) };
+    f();
+    unsafe { asm!("csrw sstatus, {}", in(reg) sstatus) };
+}
@@ -1,75 +1,128 @@
-mod csrs;
-mod detect;
-mod devices;
-mod ept;
-mod regs;
-mod sbi;
-mod smp;
-mod vcpu;
-mod vm;
-mod vm_pages;
-mod vmexit;
-
-pub use ept::NestedPageTable;
-pub use regs::GprIndex;
-pub use sbi::SbiMessage as HyperCallMsg;
-pub use smp::PerCpu;
-pub use vcpu::VCpu;
-pub use vm::VM;
-pub use vmexit::VmExitInfo;
-
-use self::csrs::{traps, ReadWriteCsr, RiscvCsrTrait, CSR};
-use self::detect::detect_h_extension;
-use self::devices::plic::PlicState;
-use self::vcpu::VmCpuRegisters;
-use sbi::BaseFunction;
-
-/// Initialize the hypervisor runtime.
-pub fn init_hv_runtime() {
-    if !detect_h_extension() {
-        panic!("H Extension not supported.")
-    }
-
-    unsafe {
-        setup_csrs();
-    }
-}
-
-/// Initialize (H)S-level CSRs to a reasonable state.
-unsafe fn setup_csrs() {
-    // Delegate some synchronous exceptions.
-    CSR.hedeleg.write_value(
-        traps::exception::INST_ADDR_MISALIGN
-            | traps::exception::BREAKPOINT
-            | traps::exception::ENV_CALL_FROM_U_OR_VU
-            | traps::exception::INST_PAGE_FAULT
-            | traps::exception::LOAD_PAGE_FAULT
-            | traps::exception::STORE_PAGE_FAULT
-            | traps::exception::ILLEGAL_INST,
-    );
-
-    // Delegate all interupts.
-    CSR.hideleg.write_value(
-        traps::interrupt::VIRTUAL_SUPERVISOR_TIMER
-            | traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL
-            | traps::interrupt::VIRTUAL_SUPERVISOR_SOFT,
-    );
-
-    // Clear all interrupts.
-    CSR.hvip.read_and_clear_bits(
-        traps::interrupt::VIRTUAL_SUPERVISOR_TIMER
-            | traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL
-            | traps::interrupt::VIRTUAL_SUPERVISOR_SOFT,
-    );
-
-    // clear all interrupts.
-    CSR.hcounteren.write_value(0xffff_ffff);
-
-    // enable interrupt
-    CSR.sie.write_value(
-        traps::interrupt::SUPERVISOR_EXTERNAL
-            | traps::interrupt::SUPERVISOR_SOFT
-            | traps::interrupt::SUPERVISOR_TIMER,
-    );
-    debug!("sie: {:#x}", CSR.sie.get_value());
-}
+mod csrs;
+mod detect;
+mod devices;
+mod ept;
+mod fp;
+mod regs;
+mod sbi;
+mod smp;
+mod vcpu;
+mod virt_inst;
+mod virt_pmu;
+mod vm;
+mod vm_pages;
+mod vmexit;
+mod vmid;
+
+pub use devices::{
+    aia::{num_guest_interrupt_files, AiaConfig},
+    plic::PlicConfig,
+    ConsoleSink, GuestInterruptFile, HostConsole, ImsicFile, MmioBus, MmioDevice,
+    VirtualInterruptController,
+};
+pub use ept::{
+    GStageMode, GStagePageTable, NestedPageTable, NestedPageTableSv48, NestedPageTableSv57,
+};
+pub use regs::{GeneralPurposeRegisters, GprIndex};
+pub use sbi::SbiMessage as HyperCallMsg;
+pub use sbi::{ResetReason, ResetType, SbiImplementation};
+pub use smp::PerCpu;
+pub use vcpu::{VCpu, VmCpuStatus};
+pub use vm::{VmConfig, VmRunOutcome, VM};
+pub use vm_pages::{ByteValued, GuestMemory, VmRegion, VmRegionType};
+pub use vmexit::{VmExitAction, VmExitHandler, VmExitInfo};
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use self::csrs::{traps, ReadWriteCsr, RiscvCsrTrait, CSR};
+use self::detect::{detect_aia, detect_h_extension, detect_sstc, detect_vector};
+use self::vcpu::VmCpuRegisters;
+use sbi::BaseFunction;
+
+/// The `henvcfg.STCE` bit, which enables `vstimecmp`.
+const HENVCFG_STCE: usize = 1 << 63;
+
+/// Initialize the hypervisor runtime on this hart. Must be called on every hart before any VM is
+/// created: the features guests can use are the ones all the harts implement.
+pub fn init_hv_runtime() {
+    if !detect_h_extension() {
+        panic!("H Extension not supported.")
+    }
+
+    unsafe {
+        setup_csrs();
+    }
+}
+
+/// Whether the harts implement Sstc, letting guests program `vstimecmp` directly.
+static HAS_SSTC: AtomicBool = AtomicBool::new(true);
+
+/// Returns whether the harts implement Sstc.
+pub(crate) fn has_sstc() -> bool {
+    HAS_SSTC.load(Ordering::Relaxed)
+}
+
+/// Whether the harts implement Ssaia, giving guests `vsiselect`, `vsireg` and `vstopei`.
+static HAS_AIA: AtomicBool = AtomicBool::new(true);
+
+/// Returns whether the harts implement Ssaia.
+pub(crate) fn has_aia() -> bool {
+    HAS_AIA.load(Ordering::Relaxed)
+}
+
+/// Initialize (H)S-level CSRs to a reasonable state.
+unsafe fn setup_csrs() {
+    // Delegate some synchronous exceptions.
+    CSR.hedeleg.write_value(
+        traps::exception::INST_ADDR_MISALIGN
+            | traps::exception::BREAKPOINT
+            | traps::exception::ENV_CALL_FROM_U_OR_VU
+            | traps::exception::INST_PAGE_FAULT
+            | traps::exception::LOAD_PAGE_FAULT
+            | traps::exception::STORE_PAGE_FAULT
+            | traps::exception::ILLEGAL_INST,
+    );
+
+    // Delegate all interupts.
+    CSR.hideleg.write_value(
+        traps::interrupt::VIRTUAL_SUPERVISOR_TIMER
+            | traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL
+            | traps::interrupt::VIRTUAL_SUPERVISOR_SOFT,
+    );
+
+    // Clear all interrupts.
+    CSR.hvip.read_and_clear_bits(
+        traps::interrupt::VIRTUAL_SUPERVISOR_TIMER
+            | traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL
+            | traps::interrupt::VIRTUAL_SUPERVISOR_SOFT,
+    );
+
+    // Only the fixed counters are readable, hardware counters are enabled when the vCPU
+    // configures them through the SBI PMU extension.
+    CSR.hcounteren.write_value(virt_pmu::FIXED_COUNTERS);
+
+    // Let guests use vstimecmp if all harts implement Sstc. Only known once every hart is set up,
+    // so `henvcfg.STCE` is set as vCPUs enter the guest.
+    HAS_SSTC.fetch_and(detect_sstc(), Ordering::Relaxed);
+
+    // Guests get their own vector state if the harts implement the V extension.
+    fp::detect_vlenb(detect_vector());
+
+    // Guests can be given interrupt files of the IMSIC if the harts implement the AIA.
+    let aia = detect_aia();
+    HAS_AIA.fetch_and(aia, Ordering::Relaxed);
+    if aia {
+        devices::aia::detect_geilen();
+    }
+
+    ept::detect_gstage_modes();
+    vmid::detect_vmid_bits();
+
+    // enable interrupt
+    CSR.sie.write_value(
+        traps::interrupt::SUPERVISOR_EXTERNAL
+            | traps::interrupt::SUPERVISOR_SOFT
+            | traps::interrupt::SUPERVISOR_TIMER,
+    );
+    debug!("sie: {:#x}", CSR.sie.get_value());
+}
@@ -1,35 +1,118 @@
-use crate::HyperResult;
-
-/// Functions defined for the Base extension
-#[derive(Clone, Copy, Debug)]
-pub enum BaseFunction {
-    /// Returns the implemented version of the SBI standard.
-    GetSepcificationVersion,
-    /// Returns the ID of the SBI implementation.
-    GetImplementationID,
-    /// Returns the version of the SBI implementation.
-    GetImplementationVersion,
-    /// Checks if the given SBI extension is supported.
-    ProbeSbiExtension(u64),
-    /// Returns the vendor that produced this machine(`mvendorid`).
-    GetMachineVendorID,
-    /// Returns the architecture implementation ID of this machine(`marchid`).
-    GetMachineArchitectureID,
-    /// Returns the ID of this machine(`mimpid`).
-    GetMachineImplementationID,
-}
-
-impl BaseFunction {
-    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
-        match args[6] {
-            0 => Ok(BaseFunction::GetSepcificationVersion),
-            1 => Ok(BaseFunction::GetImplementationID),
-            2 => Ok(BaseFunction::GetImplementationVersion),
-            3 => Ok(BaseFunction::ProbeSbiExtension(args[0] as u64)),
-            4 => Ok(BaseFunction::GetMachineVendorID),
-            5 => Ok(BaseFunction::GetMachineArchitectureID),
-            6 => Ok(BaseFunction::GetMachineImplementationID),
-            _ => Err(crate::HyperError::NotFound),
-        }
-    }
-}
+use alloc::vec::Vec;
+
+use super::{EID_DBCN, EID_SUSP};
+use crate::HyperResult;
+
+/// The SBI implementation the hypervisor presents to a guest through the Base extension: its
+/// identity, and the registry of extensions emulated for the guest.
+#[derive(Clone, Debug)]
+pub struct SbiImplementation {
+    /// The SBI specification version, major in bits 24-30 and minor in bits 0-23.
+    pub spec_version: usize,
+    /// The SBI implementation ID.
+    pub impl_id: usize,
+    /// The SBI implementation version.
+    pub impl_version: usize,
+    /// The `mvendorid` of the machine.
+    pub mvendorid: usize,
+    /// The `marchid` of the machine.
+    pub marchid: usize,
+    /// The `mimpid` of the machine.
+    pub mimpid: usize,
+    // The extension IDs reported as available, and handled.
+    extensions: Vec<usize>,
+}
+
+impl SbiImplementation {
+    /// Creates an SBI implementation that identifies as the host's firmware, on the host's
+    /// machine, with the extensions emulated by `VM::run`. It implements version 2.0 of the
+    /// specification, which defines DBCN and SUSP, whatever the host's version.
+    pub fn from_host() -> Self {
+        Self {
+            spec_version: 2 << 24,
+            impl_id: sbi_rt::get_sbi_impl_id(),
+            impl_version: sbi_rt::get_sbi_impl_version(),
+            mvendorid: sbi_rt::get_mvendorid(),
+            marchid: sbi_rt::get_marchid(),
+            mimpid: sbi_rt::get_mimpid(),
+            extensions: vec![
+                sbi_spec::legacy::LEGACY_SET_TIMER,
+                sbi_spec::legacy::LEGACY_CONSOLE_PUTCHAR,
+                sbi_spec::legacy::LEGACY_CONSOLE_GETCHAR,
+                sbi_spec::legacy::LEGACY_CLEAR_IPI,
+                sbi_spec::legacy::LEGACY_SEND_IPI,
+                sbi_spec::base::EID_BASE,
+                sbi_spec::time::EID_TIME,
+                sbi_spec::spi::EID_SPI,
+                sbi_spec::rfnc::EID_RFNC,
+                sbi_spec::hsm::EID_HSM,
+                sbi_spec::srst::EID_SRST,
+                sbi_spec::pmu::EID_PMU,
+                EID_DBCN,
+                EID_SUSP,
+            ],
+        }
+    }
+
+    /// Reports the extension `eid` as available, e.g. when a `VmExitHandler` handles it.
+    pub fn with_extension(mut self, eid: usize) -> Self {
+        if !self.extensions.contains(&eid) {
+            self.extensions.push(eid);
+        }
+        self
+    }
+
+    /// Hides the extension `eid` from the guest, its calls then fail with
+    /// `SBI_ERR_NOT_SUPPORTED`. The Base extension can't be hidden.
+    pub fn without_extension(mut self, eid: usize) -> Self {
+        if eid != sbi_spec::base::EID_BASE {
+            self.extensions.retain(|&e| e != eid);
+        }
+        self
+    }
+
+    /// Returns whether the extension `eid` is available to the guest.
+    pub fn has_extension(&self, eid: usize) -> bool {
+        self.extensions.contains(&eid)
+    }
+}
+
+impl Default for SbiImplementation {
+    fn default() -> Self {
+        Self::from_host()
+    }
+}
+
+/// Functions defined for the Base extension
+#[derive(Clone, Copy, Debug)]
+pub enum BaseFunction {
+    /// Returns the implemented version of the SBI standard.
+    GetSepcificationVersion,
+    /// Returns the ID of the SBI implementation.
+    GetImplementationID,
+    /// Returns the version of the SBI implementation.
+    GetImplementationVersion,
+    /// Checks if the given SBI extension is supported.
+    ProbeSbiExtension(u64),
+    /// Returns the vendor that produced this machine(`mvendorid`).
+    GetMachineVendorID,
+    /// Returns the architecture implementation ID of this machine(`marchid`).
+    GetMachineArchitectureID,
+    /// Returns the ID of this machine(`mimpid`).
+    GetMachineImplementationID,
+}
+
+impl BaseFunction {
+    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            0 => Ok(BaseFunction::GetSepcificationVersion),
+            1 => Ok(BaseFunction::GetImplementationID),
+            2 => Ok(BaseFunction::GetImplementationVersion),
+            3 => Ok(BaseFunction::ProbeSbiExtension(args[0] as u64)),
+            4 => Ok(BaseFunction::GetMachineVendorID),
+            5 => Ok(BaseFunction::GetMachineArchitectureID),
+            6 => Ok(BaseFunction::GetMachineImplementationID),
+            _ => Err(crate::HyperError::NotFound),
+        }
+    }
+}
@@ -1,11 +1,57 @@
-/// Functions for the Debug Console extension
-#[derive(Copy, Clone, Debug)]
-pub enum DebugConsoleFunction {
-    /// Prints the given string to the system console.
-    PutString {
-        /// The length of the string to print.
-        len: u64,
-        /// The address of the string.
-        addr: u64,
-    },
-}
+use crate::{HyperError, HyperResult};
+
+/// The extension ID of the Debug Console extension ("DBCN").
+pub const EID_DBCN: usize = 0x4442_434e;
+
+const CONSOLE_WRITE: usize = 0;
+const CONSOLE_READ: usize = 1;
+const CONSOLE_WRITE_BYTE: usize = 2;
+
+/// Functions for the Debug Console extension
+#[derive(Copy, Clone, Debug)]
+pub enum DebugConsoleFunction {
+    /// Writes the bytes of the given guest physical buffer to the console.
+    Write {
+        /// The number of bytes to write.
+        num_bytes: u64,
+        /// The lower XLEN bits of the buffer's guest physical address.
+        base_addr_lo: u64,
+        /// The upper XLEN bits of the buffer's guest physical address.
+        base_addr_hi: u64,
+    },
+    /// Reads pending console input into the given guest physical buffer, without blocking.
+    Read {
+        /// The size of the buffer.
+        num_bytes: u64,
+        /// The lower XLEN bits of the buffer's guest physical address.
+        base_addr_lo: u64,
+        /// The upper XLEN bits of the buffer's guest physical address.
+        base_addr_hi: u64,
+    },
+    /// Writes a single byte to the console.
+    WriteByte {
+        /// The byte to write.
+        byte: u8,
+    },
+}
+
+impl DebugConsoleFunction {
+    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            CONSOLE_WRITE => Ok(Self::Write {
+                num_bytes: args[0] as u64,
+                base_addr_lo: args[1] as u64,
+                base_addr_hi: args[2] as u64,
+            }),
+            CONSOLE_READ => Ok(Self::Read {
+                num_bytes: args[0] as u64,
+                base_addr_lo: args[1] as u64,
+                base_addr_hi: args[2] as u64,
+            }),
+            CONSOLE_WRITE_BYTE => Ok(Self::WriteByte {
+                byte: args[0] as u8,
+            }),
+            _ => Err(HyperError::NotFound),
+        }
+    }
+}
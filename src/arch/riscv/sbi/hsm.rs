@@ -0,0 +1,80 @@
+use sbi_spec::hsm::{HART_GET_STATUS, HART_START, HART_STOP, HART_SUSPEND};
+
+use crate::{HyperError, HyperResult};
+
+/// Functions defined for the Hart State Management extension
+#[derive(Clone, Copy, Debug)]
+pub enum HsmFunction {
+    /// Starts the given hart at `start_addr` with `opaque` in a1.
+    HartStart {
+        /// The (virtual) hart to start.
+        hart_id: u64,
+        /// The address the hart starts executing at in supervisor mode.
+        start_addr: u64,
+        /// Opaque value passed to the hart in a1.
+        opaque: u64,
+    },
+    /// Stops the calling hart.
+    HartStop,
+    /// Returns the current status of the given hart.
+    HartStatus {
+        /// The (virtual) hart to query.
+        hart_id: u64,
+    },
+    /// Suspends the calling hart.
+    HartSuspend {
+        /// The suspend type, retentive or non-retentive.
+        suspend_type: u32,
+        /// The address to resume at after a non-retentive suspend.
+        resume_addr: u64,
+        /// Opaque value passed to the hart in a1 after a non-retentive suspend.
+        opaque: u64,
+    },
+}
+
+/// The states a hart can be in as reported by `HartStatus`.
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HartState {
+    /// The hart is physically powered-up and executing normally.
+    Started = 0,
+    /// The hart is not executing in supervisor mode or any lower privilege mode.
+    Stopped = 1,
+    /// Some other hart has requested to start this hart.
+    StartPending = 2,
+    /// Some other hart has requested to stop this hart.
+    StopPending = 3,
+    /// The hart is in a platform specific suspend (or low power) state.
+    Suspended = 4,
+    /// The hart has requested to put itself in a platform specific low power state.
+    SuspendPending = 5,
+    /// An interrupt or platform specific hardware event has caused the hart to resume.
+    ResumePending = 6,
+}
+
+/// Retentive suspend: the hart resumes at the instruction after the ECALL.
+pub const HART_SUSPEND_TYPE_RETENTIVE: u32 = 0x0000_0000;
+/// Non-retentive suspend: the hart resumes at `resume_addr` as if freshly started.
+pub const HART_SUSPEND_TYPE_NON_RETENTIVE: u32 = 0x8000_0000;
+
+impl HsmFunction {
+    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            HART_START => Ok(Self::HartStart {
+                hart_id: args[0] as u64,
+                start_addr: args[1] as u64,
+                opaque: args[2] as u64,
+            }),
+            HART_STOP => Ok(Self::HartStop),
+            HART_GET_STATUS => Ok(Self::HartStatus {
+                hart_id: args[0] as u64,
+            }),
+            HART_SUSPEND => Ok(Self::HartSuspend {
+                suspend_type: args[0] as u32,
+                resume_addr: args[1] as u64,
+                opaque: args[2] as u64,
+            }),
+            _ => Err(HyperError::NotFound),
+        }
+    }
+}
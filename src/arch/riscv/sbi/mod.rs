@@ -1,87 +1,116 @@
-mod base;
-mod dbcn;
-mod pmu;
-mod rfnc;
-mod srst;
-
-use crate::{HyperError, HyperResult};
-pub use base::BaseFunction;
-use dbcn::DebugConsoleFunction;
-pub use pmu::PmuFunction;
-pub use rfnc::RemoteFenceFunction;
-use sbi_spec;
-pub use srst::ResetFunction;
-
-pub const SBI_SUCCESS: usize = 0;
-pub const SBI_ERR_FAILUER: isize = -1;
-pub const SBI_ERR_NOT_SUPPORTED: isize = -2;
-pub const SBI_ERR_INAVLID_PARAM: isize = -3;
-pub const SBI_ERR_DENIED: isize = -4;
-pub const SBI_ERR_INVALID_ADDRESS: isize = -5;
-pub const SBI_ERR_ALREADY_AVAILABLE: isize = -6;
-
-/// The values returned from an SBI function call.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct SbiReturn {
-    /// The error code(0 for success)
-    pub error_code: i64,
-    /// The return value if the operation is successful
-    pub return_value: i64,
-}
-
-/// SBI return value conventions
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum SbiReturnTyoe {
-    /// Legacy(v0.1) extensions return a single value in A0, usually with the convention that 0
-    /// is success and < 0 is an implementation defined error code.
-    Legacy(u64),
-    /// Modern extensions use the standard error code values enumerated above.
-    Standard(SbiReturn),
-}
-
-/// SBI Message used to invoke the specfified SBI extension in the firmware.
-#[derive(Clone, Copy, Debug)]
-pub enum SbiMessage {
-    /// The base SBI extension functions.
-    Base(BaseFunction),
-    /// The legacy GetChar extension.
-    GetChar,
-    /// The legacy PutChar extension.
-    PutChar(usize),
-    /// The SetTimer Extension
-    SetTimer(usize),
-    /// Handles output to the console for debug
-    DebugConsole(DebugConsoleFunction),
-    /// Handles system reset
-    Reset(ResetFunction),
-    /// The RemoteFence Extension.
-    RemoteFence(RemoteFenceFunction),
-    /// The PMU Extension
-    PMU(PmuFunction),
-}
-
-impl SbiMessage {
-    /// Creates an SbiMessage struct from the given GPRs. Intended for use from the ECALL handler
-    /// and passed the saved register state from the calling OS. A7 must contain a valid SBI
-    /// extension and the other A* registers will be interpreted based on the extension A7 selects.
-    pub fn from_regs(args: &[usize]) -> HyperResult<Self> {
-        match args[7] {
-            sbi_spec::base::EID_BASE => BaseFunction::from_regs(args).map(SbiMessage::Base),
-            sbi_spec::legacy::LEGACY_CONSOLE_PUTCHAR => Ok(SbiMessage::PutChar(args[0])),
-            sbi_spec::legacy::LEGACY_CONSOLE_GETCHAR => Ok(SbiMessage::GetChar),
-            sbi_spec::legacy::LEGACY_SET_TIMER => Ok(SbiMessage::SetTimer(args[0])),
-            sbi_spec::time::EID_TIME => Ok(SbiMessage::SetTimer(args[0])),
-            sbi_spec::srst::EID_SRST => ResetFunction::from_regs(args).map(SbiMessage::Reset),
-            sbi_spec::rfnc::EID_RFNC => {
-                RemoteFenceFunction::from_args(args).map(SbiMessage::RemoteFence)
-            }
-            sbi_spec::pmu::EID_PMU => PmuFunction::from_regs(args).map(SbiMessage::PMU),
-            _ => {
-                error!("args: {:?}", args);
-                error!("args[7]: {:#x}", args[7]);
-                error!("EID_RFENCE: {:#x}", sbi_spec::rfnc::EID_RFNC);
-                Err(HyperError::NotFound)
-            }
-        }
-    }
-}
+mod base;
+mod dbcn;
+mod hsm;
+mod pmu;
+mod rfnc;
+mod spi;
+mod srst;
+mod susp;
+
+use crate::{HyperError, HyperResult};
+pub use base::{BaseFunction, SbiImplementation};
+pub use dbcn::{DebugConsoleFunction, EID_DBCN};
+pub use hsm::{
+    HartState, HsmFunction, HART_SUSPEND_TYPE_NON_RETENTIVE, HART_SUSPEND_TYPE_RETENTIVE,
+};
+pub use pmu::PmuFunction;
+pub use rfnc::RemoteFenceFunction;
+use sbi_spec;
+pub use spi::IpiFunction;
+pub use srst::{ResetFunction, ResetReason, ResetType};
+pub use susp::{SuspendFunction, EID_SUSP, SLEEP_TYPE_SUSPEND_TO_RAM};
+
+pub const SBI_SUCCESS: usize = 0;
+pub const SBI_ERR_FAILUER: isize = -1;
+pub const SBI_ERR_NOT_SUPPORTED: isize = -2;
+pub const SBI_ERR_INAVLID_PARAM: isize = -3;
+pub const SBI_ERR_DENIED: isize = -4;
+pub const SBI_ERR_INVALID_ADDRESS: isize = -5;
+pub const SBI_ERR_ALREADY_AVAILABLE: isize = -6;
+pub const SBI_ERR_ALREADY_STARTED: isize = -7;
+pub const SBI_ERR_ALREADY_STOPPED: isize = -8;
+pub const SBI_ERR_NO_SHMEM: isize = -9;
+
+/// The values returned from an SBI function call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SbiReturn {
+    /// The error code(0 for success)
+    pub error_code: i64,
+    /// The return value if the operation is successful
+    pub return_value: i64,
+}
+
+/// SBI return value conventions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbiReturnTyoe {
+    /// Legacy(v0.1) extensions return a single value in A0, usually with the convention that 0
+    /// is success and < 0 is an implementation defined error code.
+    Legacy(u64),
+    /// Modern extensions use the standard error code values enumerated above.
+    Standard(SbiReturn),
+}
+
+/// SBI Message used to invoke the specfified SBI extension in the firmware.
+#[derive(Clone, Copy, Debug)]
+pub enum SbiMessage {
+    /// The base SBI extension functions.
+    Base(BaseFunction),
+    /// The legacy GetChar extension.
+    GetChar,
+    /// The legacy PutChar extension.
+    PutChar(usize),
+    /// The SetTimer Extension
+    SetTimer(usize),
+    /// Handles output to the console for debug
+    DebugConsole(DebugConsoleFunction),
+    /// Handles system reset
+    Reset(ResetFunction),
+    /// The RemoteFence Extension.
+    RemoteFence(RemoteFenceFunction),
+    /// The PMU Extension
+    PMU(PmuFunction),
+    /// The Hart State Management Extension
+    Hsm(HsmFunction),
+    /// The IPI Extension and the legacy IPI calls.
+    SendIpi(IpiFunction),
+    /// The System Suspend Extension
+    Suspend(SuspendFunction),
+}
+
+impl SbiMessage {
+    /// Creates an SbiMessage struct from the given GPRs. Intended for use from the ECALL handler
+    /// and passed the saved register state from the calling OS. A7 must contain a valid SBI
+    /// extension and the other A* registers will be interpreted based on the extension A7 selects.
+    pub fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[7] {
+            sbi_spec::base::EID_BASE => BaseFunction::from_regs(args).map(SbiMessage::Base),
+            sbi_spec::legacy::LEGACY_CONSOLE_PUTCHAR => Ok(SbiMessage::PutChar(args[0])),
+            sbi_spec::legacy::LEGACY_CONSOLE_GETCHAR => Ok(SbiMessage::GetChar),
+            sbi_spec::legacy::LEGACY_SET_TIMER => Ok(SbiMessage::SetTimer(args[0])),
+            sbi_spec::time::EID_TIME => Ok(SbiMessage::SetTimer(args[0])),
+            EID_DBCN => DebugConsoleFunction::from_regs(args).map(SbiMessage::DebugConsole),
+            sbi_spec::srst::EID_SRST => ResetFunction::from_regs(args).map(SbiMessage::Reset),
+            sbi_spec::rfnc::EID_RFNC => {
+                RemoteFenceFunction::from_args(args).map(SbiMessage::RemoteFence)
+            }
+            sbi_spec::pmu::EID_PMU => PmuFunction::from_regs(args).map(SbiMessage::PMU),
+            sbi_spec::hsm::EID_HSM => HsmFunction::from_regs(args).map(SbiMessage::Hsm),
+            sbi_spec::spi::EID_SPI => IpiFunction::from_regs(args).map(SbiMessage::SendIpi),
+            EID_SUSP => SuspendFunction::from_regs(args).map(SbiMessage::Suspend),
+            sbi_spec::legacy::LEGACY_SEND_IPI => {
+                Ok(SbiMessage::SendIpi(IpiFunction::LegacySendIpi {
+                    hart_mask_addr: args[0] as u64,
+                }))
+            }
+            sbi_spec::legacy::LEGACY_CLEAR_IPI => {
+                Ok(SbiMessage::SendIpi(IpiFunction::LegacyClearIpi))
+            }
+            _ => {
+                error!("args: {:?}", args);
+                error!("args[7]: {:#x}", args[7]);
+                error!("EID_RFENCE: {:#x}", sbi_spec::rfnc::EID_RFNC);
+                Err(HyperError::NotFound)
+            }
+        }
+    }
+}
@@ -1,34 +1,93 @@
-use crate::HyperResult;
-
-#[derive(Clone, Copy, Debug)]
-pub enum PmuFunction {
-    /// Returns the total of performance counters (hardware and fireware).
-    GetNumCounters,
-    /// Returns information about hardware counter specified by the inner value.
-    GetCounterInfo(u64),
-    /// Stops the couters selected by counter_index and counter_mask.
-    /// See the sbi_pmu_counter_stop documentation for details.
-    StopCounter {
-        /// Countert index base.
-        counter_index: u64,
-        /// Counter index mask.
-        counter_mask: u64,
-        /// Counter stop flags.
-        stop_flags: u64,
-    },
-}
-
-impl PmuFunction {
-    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
-        match args[6] {
-            0 => Ok(Self::GetNumCounters),
-            1 => Ok(Self::GetCounterInfo(args[0] as u64)),
-            4 => Ok(Self::StopCounter {
-                counter_index: args[0] as u64,
-                counter_mask: args[1] as u64,
-                stop_flags: args[2] as u64,
-            }),
-            _ => panic!("Unsupported yet!"),
-        }
-    }
-}
+use crate::{HyperError, HyperResult};
+
+/// Functions defined for the PMU extension
+#[derive(Clone, Copy, Debug)]
+pub enum PmuFunction {
+    /// Returns the total of performance counters (hardware and fireware).
+    GetNumCounters,
+    /// Returns information about hardware counter specified by the inner value.
+    GetCounterInfo(u64),
+    /// Finds and configures a counter from the set of counters selected by counter_index and
+    /// counter_mask that can monitor the given event.
+    ConfigMatchingCounter {
+        /// Counter index base.
+        counter_index: u64,
+        /// Counter index mask.
+        counter_mask: u64,
+        /// Counter configuration flags.
+        config_flags: u64,
+        /// The event to monitor.
+        event_index: u64,
+        /// Additional event configuration.
+        event_data: u64,
+    },
+    /// Starts the counters selected by counter_index and counter_mask.
+    StartCounter {
+        /// Counter index base.
+        counter_index: u64,
+        /// Counter index mask.
+        counter_mask: u64,
+        /// Counter start flags.
+        start_flags: u64,
+        /// The value the counters start at, with the SET_INIT_VALUE flag.
+        initial_value: u64,
+    },
+    /// Stops the couters selected by counter_index and counter_mask.
+    /// See the sbi_pmu_counter_stop documentation for details.
+    StopCounter {
+        /// Countert index base.
+        counter_index: u64,
+        /// Counter index mask.
+        counter_mask: u64,
+        /// Counter stop flags.
+        stop_flags: u64,
+    },
+    /// Reads the value of the firmware counter specified by the inner value.
+    ReadFirmwareCounter(u64),
+    /// Reads the upper 32 bits of the firmware counter specified by the inner value, RV32 only.
+    ReadFirmwareCounterHigh(u64),
+    /// Sets the shared memory used for counter snapshots.
+    SetSnapshotShmem {
+        /// The lower XLEN bits of the shared memory's guest physical address.
+        shmem_lo: u64,
+        /// The upper XLEN bits of the shared memory's guest physical address.
+        shmem_hi: u64,
+        /// Reserved flags.
+        flags: u64,
+    },
+}
+
+impl PmuFunction {
+    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            0 => Ok(Self::GetNumCounters),
+            1 => Ok(Self::GetCounterInfo(args[0] as u64)),
+            2 => Ok(Self::ConfigMatchingCounter {
+                counter_index: args[0] as u64,
+                counter_mask: args[1] as u64,
+                config_flags: args[2] as u64,
+                event_index: args[3] as u64,
+                event_data: args[4] as u64,
+            }),
+            3 => Ok(Self::StartCounter {
+                counter_index: args[0] as u64,
+                counter_mask: args[1] as u64,
+                start_flags: args[2] as u64,
+                initial_value: args[3] as u64,
+            }),
+            4 => Ok(Self::StopCounter {
+                counter_index: args[0] as u64,
+                counter_mask: args[1] as u64,
+                stop_flags: args[2] as u64,
+            }),
+            5 => Ok(Self::ReadFirmwareCounter(args[0] as u64)),
+            6 => Ok(Self::ReadFirmwareCounterHigh(args[0] as u64)),
+            7 => Ok(Self::SetSnapshotShmem {
+                shmem_lo: args[0] as u64,
+                shmem_hi: args[1] as u64,
+                flags: args[2] as u64,
+            }),
+            _ => Err(HyperError::NotFound),
+        }
+    }
+}
@@ -1,35 +1,104 @@
-use sbi_spec::rfnc::{REMOTE_FENCE_I, REMOTE_SFENCE_VMA};
-
-use crate::HyperResult;
-
-#[derive(Clone, Copy, Debug)]
-pub enum RemoteFenceFunction {
-    FenceI {
-        hart_mask: u64,
-        hart_mask_base: u64,
-    },
-    RemoteSFenceVMA {
-        hart_mask: u64,
-        hart_mask_base: u64,
-        start_addr: u64,
-        size: u64,
-    },
-}
-
-impl RemoteFenceFunction {
-    pub fn from_args(args: &[usize]) -> HyperResult<Self> {
-        match args[6] {
-            REMOTE_FENCE_I => Ok(Self::FenceI {
-                hart_mask: args[0] as u64,
-                hart_mask_base: args[1] as u64,
-            }),
-            REMOTE_SFENCE_VMA => Ok(Self::RemoteSFenceVMA {
-                hart_mask: args[0] as u64,
-                hart_mask_base: args[1] as u64,
-                start_addr: args[2] as u64,
-                size: args[3] as u64,
-            }),
-            _ => panic!("Unsupported yet!"),
-        }
-    }
-}
+use sbi_spec::rfnc::{
+    REMOTE_FENCE_I, REMOTE_HFENCE_GVMA, REMOTE_HFENCE_GVMA_VMID, REMOTE_HFENCE_VVMA,
+    REMOTE_HFENCE_VVMA_ASID, REMOTE_SFENCE_VMA, REMOTE_SFENCE_VMA_ASID,
+};
+
+use crate::{HyperError, HyperResult};
+
+#[derive(Clone, Copy, Debug)]
+pub enum RemoteFenceFunction {
+    FenceI {
+        hart_mask: u64,
+        hart_mask_base: u64,
+    },
+    RemoteSFenceVMA {
+        hart_mask: u64,
+        hart_mask_base: u64,
+        start_addr: u64,
+        size: u64,
+    },
+    RemoteSFenceVMAWithASID {
+        hart_mask: u64,
+        hart_mask_base: u64,
+        start_addr: u64,
+        size: u64,
+        asid: u64,
+    },
+    RemoteHFenceGVMAWithVMID {
+        hart_mask: u64,
+        hart_mask_base: u64,
+        start_addr: u64,
+        size: u64,
+        vmid: u64,
+    },
+    RemoteHFenceGVMA {
+        hart_mask: u64,
+        hart_mask_base: u64,
+        start_addr: u64,
+        size: u64,
+    },
+    RemoteHFenceVVMAWithASID {
+        hart_mask: u64,
+        hart_mask_base: u64,
+        start_addr: u64,
+        size: u64,
+        asid: u64,
+    },
+    RemoteHFenceVVMA {
+        hart_mask: u64,
+        hart_mask_base: u64,
+        start_addr: u64,
+        size: u64,
+    },
+}
+
+impl RemoteFenceFunction {
+    pub fn from_args(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            REMOTE_FENCE_I => Ok(Self::FenceI {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+            }),
+            REMOTE_SFENCE_VMA => Ok(Self::RemoteSFenceVMA {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+                start_addr: args[2] as u64,
+                size: args[3] as u64,
+            }),
+            REMOTE_SFENCE_VMA_ASID => Ok(Self::RemoteSFenceVMAWithASID {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+                start_addr: args[2] as u64,
+                size: args[3] as u64,
+                asid: args[4] as u64,
+            }),
+            REMOTE_HFENCE_GVMA_VMID => Ok(Self::RemoteHFenceGVMAWithVMID {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+                start_addr: args[2] as u64,
+                size: args[3] as u64,
+                vmid: args[4] as u64,
+            }),
+            REMOTE_HFENCE_GVMA => Ok(Self::RemoteHFenceGVMA {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+                start_addr: args[2] as u64,
+                size: args[3] as u64,
+            }),
+            REMOTE_HFENCE_VVMA_ASID => Ok(Self::RemoteHFenceVVMAWithASID {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+                start_addr: args[2] as u64,
+                size: args[3] as u64,
+                asid: args[4] as u64,
+            }),
+            REMOTE_HFENCE_VVMA => Ok(Self::RemoteHFenceVVMA {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+                start_addr: args[2] as u64,
+                size: args[3] as u64,
+            }),
+            _ => Err(HyperError::NotFound),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use sbi_spec::spi::SEND_IPI;
+
+use crate::{HyperError, HyperResult};
+
+/// Functions defined for the IPI extension, plus the legacy IPI calls.
+#[derive(Clone, Copy, Debug)]
+pub enum IpiFunction {
+    /// Sends an IPI to the harts selected by `hart_mask` and `hart_mask_base`.
+    SendIpi {
+        /// Hart mask, relative to `hart_mask_base`.
+        hart_mask: u64,
+        /// The hart ID bit 0 of `hart_mask` stands for, or -1 for all harts.
+        hart_mask_base: u64,
+    },
+    /// The legacy `sbi_send_ipi`, which takes the hart mask by reference.
+    LegacySendIpi {
+        /// Guest virtual address of an `unsigned long` hart mask.
+        hart_mask_addr: u64,
+    },
+    /// The legacy `sbi_clear_ipi`, which clears the calling hart's pending IPI.
+    LegacyClearIpi,
+}
+
+impl IpiFunction {
+    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            SEND_IPI => Ok(Self::SendIpi {
+                hart_mask: args[0] as u64,
+                hart_mask_base: args[1] as u64,
+            }),
+            _ => Err(HyperError::NotFound),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::{HyperError, HyperResult};
+
+/// The extension ID of the System Suspend extension ("SUSP").
+pub const EID_SUSP: usize = 0x5355_5350;
+
+const SYSTEM_SUSPEND: usize = 0;
+
+/// Suspend to RAM, the only sleep type defined by the spec. The other types are reserved or
+/// platform specific.
+pub const SLEEP_TYPE_SUSPEND_TO_RAM: u32 = 0;
+
+/// Functions for the System Suspend extension
+#[derive(Copy, Clone, Debug)]
+pub enum SuspendFunction {
+    /// Suspends the system, which requires all harts but the calling one to be stopped. The
+    /// calling hart resumes at `resume_addr` as after a non-retentive hart suspend.
+    SystemSuspend {
+        /// The sleep type.
+        sleep_type: u32,
+        /// The address to resume at.
+        resume_addr: u64,
+        /// Opaque value passed to the hart in a1 when it resumes.
+        opaque: u64,
+    },
+}
+
+impl SuspendFunction {
+    pub(crate) fn from_regs(args: &[usize]) -> HyperResult<Self> {
+        match args[6] {
+            SYSTEM_SUSPEND => Ok(Self::SystemSuspend {
+                sleep_type: args[0] as u32,
+                resume_addr: args[1] as u64,
+                opaque: args[2] as u64,
+            }),
+            _ => Err(HyperError::NotFound),
+        }
+    }
+}
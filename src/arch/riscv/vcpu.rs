@@ -1,395 +1,886 @@
-use alloc::boxed::Box;
-use core::arch::global_asm;
-use core::marker::PhantomData;
-use core::mem::size_of;
-use memoffset::offset_of;
-use tock_registers::LocalRegisterCopy;
-
-// use alloc::sync::Arc;
-use riscv::register::{htinst, htval, hvip, scause, sstatus, stval};
-
-use crate::arch::vmexit::PrivilegeLevel;
-use crate::arch::{traps, RiscvCsrTrait, CSR};
-use crate::{
-    arch::sbi::SbiMessage, GuestPageTableTrait, GuestPhysAddr, GuestVirtAddr, HostPhysAddr,
-    HyperCraftHal, VmExitInfo,
-};
-
-use super::csrs::defs::hstatus;
-use super::regs::{GeneralPurposeRegisters, GprIndex};
-// use super::Guest;
-
-/// Hypervisor GPR and CSR state which must be saved/restored when entering/exiting virtualization.
-#[derive(Default)]
-#[repr(C)]
-struct HypervisorCpuState {
-    gprs: GeneralPurposeRegisters,
-    sstatus: usize,
-    hstatus: usize,
-    scounteren: usize,
-    stvec: usize,
-    sscratch: usize,
-}
-
-/// Guest GPR and CSR state which must be saved/restored when exiting/entering virtualization.
-#[derive(Default)]
-#[repr(C)]
-struct GuestCpuState {
-    gprs: GeneralPurposeRegisters,
-    sstatus: usize,
-    hstatus: usize,
-    scounteren: usize,
-    sepc: usize,
-}
-
-/// The CSRs that are only in effect when virtualization is enabled (V=1) and must be saved and
-/// restored whenever we switch between VMs.
-#[derive(Default)]
-#[repr(C)]
-pub struct GuestVsCsrs {
-    htimedelta: usize,
-    vsstatus: usize,
-    vsie: usize,
-    vstvec: usize,
-    vsscratch: usize,
-    vsepc: usize,
-    vscause: usize,
-    vstval: usize,
-    vsatp: usize,
-    vstimecmp: usize,
-}
-
-/// Virtualized HS-level CSRs that are used to emulate (part of) the hypervisor extension for the
-/// guest.
-#[derive(Default)]
-#[repr(C)]
-pub struct GuestVirtualHsCsrs {
-    hie: usize,
-    hgeie: usize,
-    hgatp: usize,
-}
-
-/// CSRs written on an exit from virtualization that are used by the hypervisor to determine the cause
-/// of the trap.
-#[derive(Default, Clone)]
-#[repr(C)]
-pub struct VmCpuTrapState {
-    pub scause: usize,
-    pub stval: usize,
-    pub htval: usize,
-    pub htinst: usize,
-}
-
-/// (v)CPU register state that must be saved or restored when entering/exiting a VM or switching
-/// between VMs.
-#[derive(Default)]
-#[repr(C)]
-pub struct VmCpuRegisters {
-    // CPU state that's shared between our's and the guest's execution environment. Saved/restored
-    // when entering/exiting a VM.
-    hyp_regs: HypervisorCpuState,
-    guest_regs: GuestCpuState,
-
-    // CPU state that only applies when V=1, e.g. the VS-level CSRs. Saved/restored on activation of
-    // the vCPU.
-    vs_csrs: GuestVsCsrs,
-
-    // Virtualized HS-level CPU state.
-    virtual_hs_csrs: GuestVirtualHsCsrs,
-
-    // Read on VM exit.
-    trap_csrs: VmCpuTrapState,
-}
-
-#[allow(dead_code)]
-const fn hyp_gpr_offset(index: GprIndex) -> usize {
-    offset_of!(VmCpuRegisters, hyp_regs)
-        + offset_of!(HypervisorCpuState, gprs)
-        + (index as usize) * size_of::<u64>()
-}
-
-#[allow(dead_code)]
-const fn guest_gpr_offset(index: GprIndex) -> usize {
-    offset_of!(VmCpuRegisters, guest_regs)
-        + offset_of!(GuestCpuState, gprs)
-        + (index as usize) * size_of::<u64>()
-}
-
-#[allow(unused_macros)]
-macro_rules! hyp_csr_offset {
-    ($reg:tt) => {
-        offset_of!(VmCpuRegisters, hyp_regs) + offset_of!(HypervisorCpuState, $reg)
-    };
-}
-
-#[allow(unused_macros)]
-macro_rules! guest_csr_offset {
-    ($reg:tt) => {
-        offset_of!(VmCpuRegisters, guest_regs) + offset_of!(GuestCpuState, $reg)
-    };
-}
-
-global_asm!(
-    include_str!("guest.S"),
-    hyp_ra = const hyp_gpr_offset(GprIndex::RA),
-    hyp_gp = const hyp_gpr_offset(GprIndex::GP),
-    hyp_tp = const hyp_gpr_offset(GprIndex::TP),
-    hyp_s0 = const hyp_gpr_offset(GprIndex::S0),
-    hyp_s1 = const hyp_gpr_offset(GprIndex::S1),
-    hyp_a1 = const hyp_gpr_offset(GprIndex::A1),
-    hyp_a2 = const hyp_gpr_offset(GprIndex::A2),
-    hyp_a3 = const hyp_gpr_offset(GprIndex::A3),
-    hyp_a4 = const hyp_gpr_offset(GprIndex::A4),
-    hyp_a5 = const hyp_gpr_offset(GprIndex::A5),
-    hyp_a6 = const hyp_gpr_offset(GprIndex::A6),
-    hyp_a7 = const hyp_gpr_offset(GprIndex::A7),
-    hyp_s2 = const hyp_gpr_offset(GprIndex::S2),
-    hyp_s3 = const hyp_gpr_offset(GprIndex::S3),
-    hyp_s4 = const hyp_gpr_offset(GprIndex::S4),
-    hyp_s5 = const hyp_gpr_offset(GprIndex::S5),
-    hyp_s6 = const hyp_gpr_offset(GprIndex::S6),
-    hyp_s7 = const hyp_gpr_offset(GprIndex::S7),
-    hyp_s8 = const hyp_gpr_offset(GprIndex::S8),
-    hyp_s9 = const hyp_gpr_offset(GprIndex::S9),
-    hyp_s10 = const hyp_gpr_offset(GprIndex::S10),
-    hyp_s11 = const hyp_gpr_offset(GprIndex::S11),
-    hyp_sp = const hyp_gpr_offset(GprIndex::SP),
-    hyp_sstatus = const hyp_csr_offset!(sstatus),
-    hyp_hstatus = const hyp_csr_offset!(hstatus),
-    hyp_scounteren = const hyp_csr_offset!(scounteren),
-    hyp_stvec = const hyp_csr_offset!(stvec),
-    hyp_sscratch = const hyp_csr_offset!(sscratch),
-    guest_ra = const guest_gpr_offset(GprIndex::RA),
-    guest_gp = const guest_gpr_offset(GprIndex::GP),
-    guest_tp = const guest_gpr_offset(GprIndex::TP),
-    guest_s0 = const guest_gpr_offset(GprIndex::S0),
-    guest_s1 = const guest_gpr_offset(GprIndex::S1),
-    guest_a0 = const guest_gpr_offset(GprIndex::A0),
-    guest_a1 = const guest_gpr_offset(GprIndex::A1),
-    guest_a2 = const guest_gpr_offset(GprIndex::A2),
-    guest_a3 = const guest_gpr_offset(GprIndex::A3),
-    guest_a4 = const guest_gpr_offset(GprIndex::A4),
-    guest_a5 = const guest_gpr_offset(GprIndex::A5),
-    guest_a6 = const guest_gpr_offset(GprIndex::A6),
-    guest_a7 = const guest_gpr_offset(GprIndex::A7),
-    guest_s2 = const guest_gpr_offset(GprIndex::S2),
-    guest_s3 = const guest_gpr_offset(GprIndex::S3),
-    guest_s4 = const guest_gpr_offset(GprIndex::S4),
-    guest_s5 = const guest_gpr_offset(GprIndex::S5),
-    guest_s6 = const guest_gpr_offset(GprIndex::S6),
-    guest_s7 = const guest_gpr_offset(GprIndex::S7),
-    guest_s8 = const guest_gpr_offset(GprIndex::S8),
-    guest_s9 = const guest_gpr_offset(GprIndex::S9),
-    guest_s10 = const guest_gpr_offset(GprIndex::S10),
-    guest_s11 = const guest_gpr_offset(GprIndex::S11),
-    guest_t0 = const guest_gpr_offset(GprIndex::T0),
-    guest_t1 = const guest_gpr_offset(GprIndex::T1),
-    guest_t2 = const guest_gpr_offset(GprIndex::T2),
-    guest_t3 = const guest_gpr_offset(GprIndex::T3),
-    guest_t4 = const guest_gpr_offset(GprIndex::T4),
-    guest_t5 = const guest_gpr_offset(GprIndex::T5),
-    guest_t6 = const guest_gpr_offset(GprIndex::T6),
-    guest_sp = const guest_gpr_offset(GprIndex::SP),
-
-    guest_sstatus = const guest_csr_offset!(sstatus),
-    guest_hstatus = const guest_csr_offset!(hstatus),
-    guest_scounteren = const guest_csr_offset!(scounteren),
-    guest_sepc = const guest_csr_offset!(sepc),
-
-);
-
-extern "C" {
-    fn _run_guest(state: *mut VmCpuRegisters);
-}
-
-pub enum VmCpuStatus {
-    /// The vCPU is not powered on.
-    PoweredOff,
-    /// The vCPU is available to be run.
-    Runnable,
-    /// The vCPU has benn claimed exclusively for running on a (physical) CPU.
-    Running,
-}
-
-#[derive(Default)]
-/// A virtual CPU within a guest
-pub struct VCpu<H: HyperCraftHal> {
-    vcpu_id: usize,
-    regs: VmCpuRegisters,
-    // gpt: G,
-    // pub guest: Arc<Guest>,
-    marker: PhantomData<H>,
-}
-
-impl<H: HyperCraftHal> VCpu<H> {
-    /// Create a new vCPU
-    pub fn new(vcpu_id: usize, entry: GuestPhysAddr) -> Self {
-        let mut regs = VmCpuRegisters::default();
-        // Set hstatus
-        let mut hstatus = LocalRegisterCopy::<usize, hstatus::Register>::new(
-            riscv::register::hstatus::read().bits(),
-        );
-        hstatus.modify(hstatus::spv::Supervisor);
-        // Set SPVP bit in order to accessing VS-mode memory from HS-mode.
-        hstatus.modify(hstatus::spvp::Supervisor);
-        CSR.hstatus.write_value(hstatus.get());
-        regs.guest_regs.hstatus = hstatus.get();
-
-        // Set sstatus
-        let mut sstatus = sstatus::read();
-        sstatus.set_spp(sstatus::SPP::Supervisor);
-        regs.guest_regs.sstatus = sstatus.bits();
-
-        regs.guest_regs.gprs.set_reg(GprIndex::A0, 0);
-        regs.guest_regs.gprs.set_reg(GprIndex::A1, 0x9000_0000);
-
-        // Set entry
-        regs.guest_regs.sepc = entry;
-        Self {
-            vcpu_id,
-            regs,
-            // gpt,
-            marker: PhantomData,
-        }
-    }
-
-    /// Initialize nested mmu.
-    pub fn init_page_map(&mut self, token: usize) {
-        // Set hgatp
-        // TODO: Sv39 currently, but should be configurable
-        self.regs.virtual_hs_csrs.hgatp = token;
-        unsafe {
-            core::arch::asm!(
-                "csrw hgatp, {hgatp}",
-                hgatp = in(reg) self.regs.virtual_hs_csrs.hgatp,
-            );
-            core::arch::riscv64::hfence_gvma_all();
-        }
-    }
-
-    /// Restore vCPU registers from the guest's GPRs
-    pub fn restore_gprs(&mut self, gprs: &GeneralPurposeRegisters) {
-        for index in 0..32 {
-            self.regs.guest_regs.gprs.set_reg(
-                GprIndex::from_raw(index).unwrap(),
-                gprs.reg(GprIndex::from_raw(index).unwrap()),
-            )
-        }
-    }
-
-    /// Save vCPU registers to the guest's GPRs
-    pub fn save_gprs(&self, gprs: &mut GeneralPurposeRegisters) {
-        for index in 0..32 {
-            gprs.set_reg(
-                GprIndex::from_raw(index).unwrap(),
-                self.regs
-                    .guest_regs
-                    .gprs
-                    .reg(GprIndex::from_raw(index).unwrap()),
-            );
-        }
-    }
-
-    /// Runs this vCPU until traps.
-    pub fn run(&mut self) -> VmExitInfo {
-        let regs = &mut self.regs;
-        unsafe {
-            // Safe to run the guest as it only touches memory assigned to it by being owned
-            // by its page table
-            _run_guest(regs);
-        }
-        // Save off the trap information
-        regs.trap_csrs.scause = scause::read().bits();
-        regs.trap_csrs.stval = stval::read();
-        regs.trap_csrs.htval = htval::read();
-        regs.trap_csrs.htinst = htinst::read();
-
-        let scause = scause::read();
-        use scause::{Exception, Interrupt, Trap};
-        match scause.cause() {
-            Trap::Exception(Exception::VirtualSupervisorEnvCall) => {
-                let sbi_msg = SbiMessage::from_regs(regs.guest_regs.gprs.a_regs()).ok();
-                VmExitInfo::Ecall(sbi_msg)
-            }
-            Trap::Interrupt(Interrupt::SupervisorTimer) => VmExitInfo::TimerInterruptEmulation,
-            Trap::Interrupt(Interrupt::SupervisorExternal) => {
-                VmExitInfo::ExternalInterruptEmulation
-            }
-            Trap::Exception(Exception::LoadGuestPageFault)
-            | Trap::Exception(Exception::StoreGuestPageFault) => {
-                let fault_addr = regs.trap_csrs.htval << 2 | regs.trap_csrs.stval & 0x3;
-                // debug!(
-                //     "fault_addr: {:#x}, htval: {:#x}, stval: {:#x}, sepc: {:#x}, scause: {:?}",
-                //     fault_addr,
-                //     regs.trap_csrs.htval,
-                //     regs.trap_csrs.stval,
-                //     regs.guest_regs.sepc,
-                //     scause.cause()
-                // );
-                VmExitInfo::PageFault {
-                    fault_addr,
-                    // Note that this address is not necessarily guest virtual as the guest may or
-                    // may not have 1st-stage translation enabled in VSATP. We still use GuestVirtAddr
-                    // here though to distinguish it from addresses (e.g. in HTVAL, or passed via a
-                    // TEECALL) which are exclusively guest-physical. Furthermore we only access guest
-                    // instructions via the HLVX instruction, which will take the VSATP translation
-                    // mode into account.
-                    falut_pc: regs.guest_regs.sepc,
-                    inst: regs.trap_csrs.htinst as u32,
-                    priv_level: PrivilegeLevel::from_hstatus(regs.guest_regs.hstatus),
-                }
-            }
-            _ => {
-                panic!(
-                    "Unhandled trap: {:?}, sepc: {:#x}, stval: {:#x}",
-                    scause.cause(),
-                    regs.guest_regs.sepc,
-                    regs.trap_csrs.stval
-                );
-            }
-        }
-    }
-
-    /// Gets one of the vCPU's general purpose registers.
-    pub fn get_gpr(&self, index: GprIndex) -> usize {
-        self.regs.guest_regs.gprs.reg(index)
-    }
-
-    /// Set one of the vCPU's general purpose register.
-    pub fn set_gpr(&mut self, index: GprIndex, val: usize) {
-        self.regs.guest_regs.gprs.set_reg(index, val);
-    }
-
-    /// Advance guest pc by `instr_len` bytes
-    pub fn advance_pc(&mut self, instr_len: usize) {
-        self.regs.guest_regs.sepc += instr_len
-    }
-
-    /// Gets the vCPU's id.
-    pub fn vcpu_id(&self) -> usize {
-        self.vcpu_id
-    }
-
-    /// Gets the vCPU's registers.
-    pub fn regs(&mut self) -> &mut VmCpuRegisters {
-        &mut self.regs
-    }
-}
-
-// Private methods implements
-impl<H: HyperCraftHal> VCpu<H> {
-    /// Delivers the given exception to the vCPU, setting its register state
-    /// to handle the trap the next time it is run.
-    fn inject_exception(&mut self) {
-        unsafe {
-            core::arch::asm!(
-                "csrw vsepc, {hyp_sepc}",
-                "csrw vscause, {hyp_scause}",
-                "csrr {guest_sepc}, vstvec",
-                hyp_sepc = in(reg) self.regs.guest_regs.sepc,
-                hyp_scause = in(reg) self.regs.trap_csrs.scause,
-                guest_sepc = out(reg) self.regs.guest_regs.sepc,
-            );
-        }
-    }
-}
+use alloc::boxed::Box;
+use core::arch::global_asm;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use memoffset::offset_of;
+use tock_registers::LocalRegisterCopy;
+
+// use alloc::sync::Arc;
+use riscv::register::{htinst, htval, hvip, mcause, scause, sstatus, stval};
+
+use crate::arch::vmexit::{GuestAccessType, PrivilegeLevel};
+use crate::arch::{has_aia, has_sstc, traps, RiscvCsrTrait, CSR, HENVCFG_STCE};
+use crate::{
+    arch::sbi::SbiMessage, timer::VCpuTimer, GuestPageTableTrait, GuestPhysAddr, GuestVirtAddr,
+    HostPhysAddr, HyperCraftHal, VmExitInfo,
+};
+
+use super::csrs::defs::{hstatus, sstatus as sstatus_defs};
+use super::devices::GuestInterruptFile;
+use super::fp::{
+    self, GuestFpState, GuestVectorState, SSTATUS_FS, SSTATUS_FS_CLEAN, SSTATUS_FS_DIRTY,
+    SSTATUS_FS_INITIAL, SSTATUS_VS, SSTATUS_VS_CLEAN, SSTATUS_VS_DIRTY, SSTATUS_VS_INITIAL,
+};
+use super::regs::{GeneralPurposeRegisters, GprIndex};
+use super::virt_pmu::{VirtualPmu, FIXED_COUNTERS};
+use super::vmid::{HGATP_VMID_MASK, HGATP_VMID_SHIFT};
+// use super::Guest;
+
+/// Hypervisor GPR and CSR state which must be saved/restored when entering/exiting virtualization.
+#[derive(Default)]
+#[repr(C)]
+struct HypervisorCpuState {
+    gprs: GeneralPurposeRegisters,
+    sstatus: usize,
+    hstatus: usize,
+    scounteren: usize,
+    stvec: usize,
+    sscratch: usize,
+}
+
+/// Guest GPR and CSR state which must be saved/restored when exiting/entering virtualization.
+#[derive(Default)]
+#[repr(C)]
+struct GuestCpuState {
+    gprs: GeneralPurposeRegisters,
+    sstatus: usize,
+    hstatus: usize,
+    scounteren: usize,
+    sepc: usize,
+}
+
+/// The CSRs that are only in effect when virtualization is enabled (V=1) and must be saved and
+/// restored whenever we switch between VMs.
+#[derive(Default)]
+#[repr(C)]
+pub struct GuestVsCsrs {
+    htimedelta: usize,
+    vsstatus: usize,
+    vsie: usize,
+    vstvec: usize,
+    vsscratch: usize,
+    vsepc: usize,
+    vscause: usize,
+    vstval: usize,
+    vsatp: usize,
+    vstimecmp: usize,
+    // The VS-level interrupts asserted in `hvip`.
+    hvip: usize,
+    // Only with Ssaia.
+    vsiselect: usize,
+}
+
+/// Virtualized HS-level CSRs that are used to emulate (part of) the hypervisor extension for the
+/// guest.
+#[derive(Default)]
+#[repr(C)]
+pub struct GuestVirtualHsCsrs {
+    hie: usize,
+    hgeie: usize,
+    hgatp: usize,
+}
+
+/// CSRs written on an exit from virtualization that are used by the hypervisor to determine the cause
+/// of the trap.
+#[derive(Default, Clone)]
+#[repr(C)]
+pub struct VmCpuTrapState {
+    pub scause: usize,
+    pub stval: usize,
+    pub htval: usize,
+    pub htinst: usize,
+}
+
+/// (v)CPU register state that must be saved or restored when entering/exiting a VM or switching
+/// between VMs.
+#[derive(Default)]
+#[repr(C)]
+pub struct VmCpuRegisters {
+    // CPU state that's shared between our's and the guest's execution environment. Saved/restored
+    // when entering/exiting a VM.
+    hyp_regs: HypervisorCpuState,
+    guest_regs: GuestCpuState,
+
+    // CPU state that only applies when V=1, e.g. the VS-level CSRs. Saved/restored on activation of
+    // the vCPU.
+    vs_csrs: GuestVsCsrs,
+    fp: GuestFpState,
+    vector: GuestVectorState,
+
+    // Virtualized HS-level CPU state.
+    virtual_hs_csrs: GuestVirtualHsCsrs,
+
+    // Read on VM exit.
+    trap_csrs: VmCpuTrapState,
+}
+
+#[allow(dead_code)]
+const fn hyp_gpr_offset(index: GprIndex) -> usize {
+    offset_of!(VmCpuRegisters, hyp_regs)
+        + offset_of!(HypervisorCpuState, gprs)
+        + (index as usize) * size_of::<u64>()
+}
+
+#[allow(dead_code)]
+const fn guest_gpr_offset(index: GprIndex) -> usize {
+    offset_of!(VmCpuRegisters, guest_regs)
+        + offset_of!(GuestCpuState, gprs)
+        + (index as usize) * size_of::<u64>()
+}
+
+#[allow(unused_macros)]
+macro_rules! hyp_csr_offset {
+    ($reg:tt) => {
+        offset_of!(VmCpuRegisters, hyp_regs) + offset_of!(HypervisorCpuState, $reg)
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! guest_csr_offset {
+    ($reg:tt) => {
+        offset_of!(VmCpuRegisters, guest_regs) + offset_of!(GuestCpuState, $reg)
+    };
+}
+
+global_asm!(
+    include_str!("guest.S"),
+    hyp_ra = const hyp_gpr_offset(GprIndex::RA),
+    hyp_gp = const hyp_gpr_offset(GprIndex::GP),
+    hyp_tp = const hyp_gpr_offset(GprIndex::TP),
+    hyp_s0 = const hyp_gpr_offset(GprIndex::S0),
+    hyp_s1 = const hyp_gpr_offset(GprIndex::S1),
+    hyp_a1 = const hyp_gpr_offset(GprIndex::A1),
+    hyp_a2 = const hyp_gpr_offset(GprIndex::A2),
+    hyp_a3 = const hyp_gpr_offset(GprIndex::A3),
+    hyp_a4 = const hyp_gpr_offset(GprIndex::A4),
+    hyp_a5 = const hyp_gpr_offset(GprIndex::A5),
+    hyp_a6 = const hyp_gpr_offset(GprIndex::A6),
+    hyp_a7 = const hyp_gpr_offset(GprIndex::A7),
+    hyp_s2 = const hyp_gpr_offset(GprIndex::S2),
+    hyp_s3 = const hyp_gpr_offset(GprIndex::S3),
+    hyp_s4 = const hyp_gpr_offset(GprIndex::S4),
+    hyp_s5 = const hyp_gpr_offset(GprIndex::S5),
+    hyp_s6 = const hyp_gpr_offset(GprIndex::S6),
+    hyp_s7 = const hyp_gpr_offset(GprIndex::S7),
+    hyp_s8 = const hyp_gpr_offset(GprIndex::S8),
+    hyp_s9 = const hyp_gpr_offset(GprIndex::S9),
+    hyp_s10 = const hyp_gpr_offset(GprIndex::S10),
+    hyp_s11 = const hyp_gpr_offset(GprIndex::S11),
+    hyp_sp = const hyp_gpr_offset(GprIndex::SP),
+    hyp_sstatus = const hyp_csr_offset!(sstatus),
+    hyp_hstatus = const hyp_csr_offset!(hstatus),
+    hyp_scounteren = const hyp_csr_offset!(scounteren),
+    hyp_stvec = const hyp_csr_offset!(stvec),
+    hyp_sscratch = const hyp_csr_offset!(sscratch),
+    guest_ra = const guest_gpr_offset(GprIndex::RA),
+    guest_gp = const guest_gpr_offset(GprIndex::GP),
+    guest_tp = const guest_gpr_offset(GprIndex::TP),
+    guest_s0 = const guest_gpr_offset(GprIndex::S0),
+    guest_s1 = const guest_gpr_offset(GprIndex::S1),
+    guest_a0 = const guest_gpr_offset(GprIndex::A0),
+    guest_a1 = const guest_gpr_offset(GprIndex::A1),
+    guest_a2 = const guest_gpr_offset(GprIndex::A2),
+    guest_a3 = const guest_gpr_offset(GprIndex::A3),
+    guest_a4 = const guest_gpr_offset(GprIndex::A4),
+    guest_a5 = const guest_gpr_offset(GprIndex::A5),
+    guest_a6 = const guest_gpr_offset(GprIndex::A6),
+    guest_a7 = const guest_gpr_offset(GprIndex::A7),
+    guest_s2 = const guest_gpr_offset(GprIndex::S2),
+    guest_s3 = const guest_gpr_offset(GprIndex::S3),
+    guest_s4 = const guest_gpr_offset(GprIndex::S4),
+    guest_s5 = const guest_gpr_offset(GprIndex::S5),
+    guest_s6 = const guest_gpr_offset(GprIndex::S6),
+    guest_s7 = const guest_gpr_offset(GprIndex::S7),
+    guest_s8 = const guest_gpr_offset(GprIndex::S8),
+    guest_s9 = const guest_gpr_offset(GprIndex::S9),
+    guest_s10 = const guest_gpr_offset(GprIndex::S10),
+    guest_s11 = const guest_gpr_offset(GprIndex::S11),
+    guest_t0 = const guest_gpr_offset(GprIndex::T0),
+    guest_t1 = const guest_gpr_offset(GprIndex::T1),
+    guest_t2 = const guest_gpr_offset(GprIndex::T2),
+    guest_t3 = const guest_gpr_offset(GprIndex::T3),
+    guest_t4 = const guest_gpr_offset(GprIndex::T4),
+    guest_t5 = const guest_gpr_offset(GprIndex::T5),
+    guest_t6 = const guest_gpr_offset(GprIndex::T6),
+    guest_sp = const guest_gpr_offset(GprIndex::SP),
+
+    guest_sstatus = const guest_csr_offset!(sstatus),
+    guest_hstatus = const guest_csr_offset!(hstatus),
+    guest_scounteren = const guest_csr_offset!(scounteren),
+    guest_sepc = const guest_csr_offset!(sepc),
+
+);
+
+extern "C" {
+    fn _run_guest(state: *mut VmCpuRegisters);
+}
+
+/// The VS-level interrupts in `hvip`.
+const VS_INTERRUPTS: usize = traps::interrupt::VIRTUAL_SUPERVISOR_SOFT
+    | traps::interrupt::VIRTUAL_SUPERVISOR_TIMER
+    | traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL;
+
+/// Returns whether a virtual interrupt enabled in `vsie` is pending, given `hip` and `hgeip` as
+/// read outside the guest, and the vCPU's guest interrupt file if any.
+fn wakeup_irq_pending(
+    hip: usize,
+    vsie: usize,
+    hgeip: usize,
+    guest_file: Option<GuestInterruptFile>,
+) -> bool {
+    // The VS-level bits of `hip` are one above the matching S-level bits of `vsie`.
+    let mut pending = (hip & VS_INTERRUPTS) >> 1;
+    if let Some(file) = guest_file {
+        if hgeip & (1 << file.guest_index) != 0 {
+            pending |= traps::interrupt::SUPERVISOR_EXTERNAL;
+        }
+    }
+    pending & vsie != 0
+}
+
+/// A queued `fence.i`.
+pub(crate) const PENDING_FENCE_I: usize = 1 << 0;
+/// A queued flush of all the VM's VS-stage TLB entries.
+pub(crate) const PENDING_HFENCE_VVMA: usize = 1 << 1;
+/// A queued flush of all the VM's G-stage TLB entries.
+pub(crate) const PENDING_HFENCE_GVMA: usize = 1 << 2;
+
+/// The run state of a vCPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VmCpuStatus {
+    /// The vCPU is not powered on.
+    #[default]
+    PoweredOff,
+    /// The vCPU is available to be run.
+    Runnable,
+    /// The vCPU has benn claimed exclusively for running on a (physical) CPU.
+    Running,
+    /// The vCPU suspended itself through SBI HSM `hart_suspend` or SUSP, and waits for an
+    /// interrupt to resume.
+    Suspended,
+}
+
+#[derive(Default)]
+/// A virtual CPU within a guest
+pub struct VCpu<H: HyperCraftHal> {
+    vcpu_id: usize,
+    // The guest physical address the boot vCPU enters at creation and after a reset.
+    entry: GuestPhysAddr,
+    regs: VmCpuRegisters,
+    status: VmCpuStatus,
+    // Virtual interrupts (`hvip` bits) to assert the next time the vCPU runs.
+    pending_irqs: usize,
+    // The hart ID of the physical CPU this vCPU runs or last ran on, if it ran.
+    pcpu_id: Option<usize>,
+    // Fences (`PENDING_*`) to do on the hart the vCPU next runs on, before entering the guest.
+    pending_fences: usize,
+    // The timer armed through SBI `set_timer` without Sstc.
+    timer: VCpuTimer,
+    pmu: VirtualPmu,
+    // The IMSIC guest interrupt file the guest's external interrupts are delivered through, if
+    // any. Its bit is only set in `hgeie` while the hart waits for the suspended vCPU's wakeup.
+    guest_file: Option<GuestInterruptFile>,
+    // gpt: G,
+    // pub guest: Arc<Guest>,
+    marker: PhantomData<H>,
+}
+
+impl<H: HyperCraftHal> VCpu<H> {
+    /// Create a new vCPU
+    pub fn new(vcpu_id: usize, entry: GuestPhysAddr) -> Self {
+        let mut regs = VmCpuRegisters::default();
+        // Set hstatus
+        let mut hstatus = LocalRegisterCopy::<usize, hstatus::Register>::new(
+            riscv::register::hstatus::read().bits(),
+        );
+        hstatus.modify(hstatus::spv::Supervisor);
+        // Set SPVP bit in order to accessing VS-mode memory from HS-mode.
+        hstatus.modify(hstatus::spvp::Supervisor);
+        // Trap WFI so that an idle vCPU gives up its physical hart.
+        hstatus.modify(hstatus::vtw::SET);
+        CSR.hstatus.write_value(hstatus.get());
+        regs.guest_regs.hstatus = hstatus.get();
+
+        // Only the boot vCPU starts out runnable, the others wait for an SBI HSM `hart_start`.
+        let status = if vcpu_id == 0 {
+            VmCpuStatus::Runnable
+        } else {
+            VmCpuStatus::PoweredOff
+        };
+        let mut vcpu = Self {
+            vcpu_id,
+            entry,
+            regs,
+            status,
+            pending_irqs: 0,
+            pcpu_id: None,
+            pending_fences: 0,
+            timer: VCpuTimer::new(),
+            pmu: VirtualPmu::new(),
+            guest_file: None,
+            // gpt,
+            marker: PhantomData,
+        };
+        vcpu.reset_regs();
+        vcpu
+    }
+
+    /// Sets the guest registers to their state at creation, with the vCPU about to enter
+    /// `entry`.
+    fn reset_regs(&mut self) {
+        let regs = &mut self.regs;
+        regs.guest_regs.gprs = GeneralPurposeRegisters::default();
+
+        // Set sstatus
+        let mut sstatus = sstatus::read();
+        sstatus.set_spp(sstatus::SPP::Supervisor);
+        // The guest may use the FP and vector units whatever the host does, starting from zeroed
+        // registers.
+        let mut sstatus = sstatus.bits() & !(SSTATUS_FS | SSTATUS_VS) | SSTATUS_FS_INITIAL;
+        if fp::has_vector() {
+            sstatus |= SSTATUS_VS_INITIAL;
+        }
+        regs.guest_regs.sstatus = sstatus;
+        regs.fp = GuestFpState::default();
+        regs.vector = GuestVectorState::default();
+
+        regs.guest_regs.gprs.set_reg(GprIndex::A0, self.vcpu_id);
+        regs.guest_regs.gprs.set_reg(GprIndex::A1, 0x9000_0000);
+
+        // Set entry
+        regs.guest_regs.sepc = self.entry;
+
+        // The guest timer stays off until the guest programs it.
+        regs.vs_csrs = GuestVsCsrs {
+            htimedelta: regs.vs_csrs.htimedelta,
+            vstimecmp: usize::MAX,
+            ..Default::default()
+        };
+    }
+
+    /// Resets the vCPU to its state at creation for a system reset of the VM: the boot vCPU is
+    /// runnable at the entry point and the others are powered off. The vCPU must not be loaded
+    /// on a hart.
+    pub fn reset(&mut self) {
+        self.reset_regs();
+        self.pending_irqs = 0;
+        self.timer.clear();
+        self.pmu.reset();
+        self.status = if self.vcpu_id == 0 {
+            VmCpuStatus::Runnable
+        } else {
+            VmCpuStatus::PoweredOff
+        };
+    }
+
+    /// Loads the vCPU's state that only applies when V=1 onto this hart before running it here:
+    /// the VS-level CSRs, its virtual interrupts in `hvip`, its FP registers and its PMU counters.
+    /// `htimedelta` and `vstimecmp` are written on every entry by `run`.
+    pub fn load(&mut self) {
+        let vs_csrs = &self.regs.vs_csrs;
+        unsafe {
+            core::arch::asm!(
+                "csrw vsstatus, {vsstatus}",
+                "csrw vsie, {vsie}",
+                "csrw vstvec, {vstvec}",
+                "csrw vsscratch, {vsscratch}",
+                "csrw vsepc, {vsepc}",
+                "csrw vscause, {vscause}",
+                "csrw vstval, {vstval}",
+                "csrw vsatp, {vsatp}",
+                vsstatus = in(reg) vs_csrs.vsstatus,
+                vsie = in(reg) vs_csrs.vsie,
+                vstvec = in(reg) vs_csrs.vstvec,
+                vsscratch = in(reg) vs_csrs.vsscratch,
+                vsepc = in(reg) vs_csrs.vsepc,
+                vscause = in(reg) vs_csrs.vscause,
+                vstval = in(reg) vs_csrs.vstval,
+                vsatp = in(reg) vs_csrs.vsatp,
+            );
+        }
+        if has_aia() {
+            unsafe { core::arch::asm!("csrw 0x250, {}", in(reg) vs_csrs.vsiselect) };
+            // vsiselect
+        }
+        CSR.hvip.read_and_clear_bits(VS_INTERRUPTS);
+        CSR.hvip.read_and_set_bits(vs_csrs.hvip);
+        let sstatus = self.regs.guest_regs.sstatus;
+        if sstatus & SSTATUS_FS != 0 {
+            self.regs.fp.restore();
+        }
+        if sstatus & SSTATUS_VS != 0 {
+            self.regs.vector.restore();
+        }
+        self.pmu.restore();
+        CSR.hcounteren
+            .write_value(FIXED_COUNTERS | self.pmu.counter_enable_mask());
+    }
+
+    /// Saves the vCPU's state loaded by `load` when it leaves this hart.
+    pub fn put(&mut self) {
+        let vs_csrs = &mut self.regs.vs_csrs;
+        unsafe {
+            core::arch::asm!(
+                "csrr {vsstatus}, vsstatus",
+                "csrr {vsie}, vsie",
+                "csrr {vstvec}, vstvec",
+                "csrr {vsscratch}, vsscratch",
+                "csrr {vsepc}, vsepc",
+                "csrr {vscause}, vscause",
+                "csrr {vstval}, vstval",
+                "csrr {vsatp}, vsatp",
+                vsstatus = out(reg) vs_csrs.vsstatus,
+                vsie = out(reg) vs_csrs.vsie,
+                vstvec = out(reg) vs_csrs.vstvec,
+                vsscratch = out(reg) vs_csrs.vsscratch,
+                vsepc = out(reg) vs_csrs.vsepc,
+                vscause = out(reg) vs_csrs.vscause,
+                vstval = out(reg) vs_csrs.vstval,
+                vsatp = out(reg) vs_csrs.vsatp,
+            );
+        }
+        if has_aia() {
+            unsafe { core::arch::asm!("csrr {}, 0x250", out(reg) vs_csrs.vsiselect) };
+            // vsiselect
+        }
+        vs_csrs.hvip = CSR.hvip.get_value() & VS_INTERRUPTS;
+        CSR.hvip.read_and_clear_bits(VS_INTERRUPTS);
+        // Only the state the guest changed since it was loaded needs saving.
+        let sstatus = &mut self.regs.guest_regs.sstatus;
+        if *sstatus & SSTATUS_FS == SSTATUS_FS_DIRTY {
+            self.regs.fp.save();
+            *sstatus = *sstatus & !SSTATUS_FS | SSTATUS_FS_CLEAN;
+        }
+        if *sstatus & SSTATUS_VS == SSTATUS_VS_DIRTY {
+            self.regs.vector.save();
+            *sstatus = *sstatus & !SSTATUS_VS | SSTATUS_VS_CLEAN;
+        }
+        self.pmu.save();
+    }
+
+    /// Initialize nested mmu with the `hgatp` value `token`, holding the G-stage mode and root
+    /// page table. The page table is loaded into `hgatp` when the vCPU runs.
+    pub fn init_page_map(&mut self, token: usize) {
+        self.regs.virtual_hs_csrs.hgatp = token & !HGATP_VMID_MASK;
+    }
+
+    /// Loads the G-stage page table into `hgatp`, tagged with `vmid`, before running the vCPU.
+    pub(crate) fn load_hgatp(&mut self, vmid: usize) {
+        let hgatp = &mut self.regs.virtual_hs_csrs.hgatp;
+        *hgatp = (*hgatp & !HGATP_VMID_MASK) | (vmid << HGATP_VMID_SHIFT);
+        unsafe {
+            core::arch::asm!(
+                "csrw hgatp, {hgatp}",
+                hgatp = in(reg) *hgatp,
+            );
+        }
+    }
+
+    /// Restore vCPU registers from the guest's GPRs
+    pub fn restore_gprs(&mut self, gprs: &GeneralPurposeRegisters) {
+        for index in 0..32 {
+            self.regs.guest_regs.gprs.set_reg(
+                GprIndex::from_raw(index).unwrap(),
+                gprs.reg(GprIndex::from_raw(index).unwrap()),
+            )
+        }
+    }
+
+    /// Save vCPU registers to the guest's GPRs
+    pub fn save_gprs(&self, gprs: &mut GeneralPurposeRegisters) {
+        for index in 0..32 {
+            gprs.set_reg(
+                GprIndex::from_raw(index).unwrap(),
+                self.regs
+                    .guest_regs
+                    .gprs
+                    .reg(GprIndex::from_raw(index).unwrap()),
+            );
+        }
+    }
+
+    /// Runs this vCPU until traps.
+    pub fn run(&mut self) -> VmExitInfo {
+        self.flush_pending_irqs();
+        self.run_pending_fences();
+
+        let regs = &mut self.regs;
+        unsafe {
+            // The guest's time base and, with Sstc, its timer follow the vCPU across harts.
+            core::arch::asm!("csrw htimedelta, {}", in(reg) regs.vs_csrs.htimedelta);
+            if has_sstc() {
+                core::arch::asm!(
+                    "csrs henvcfg, {stce}",
+                    "csrw vstimecmp, {vstimecmp}",
+                    stce = in(reg) HENVCFG_STCE,
+                    vstimecmp = in(reg) regs.vs_csrs.vstimecmp,
+                );
+            }
+            // Safe to run the guest as it only touches memory assigned to it by being owned
+            // by its page table
+            _run_guest(regs);
+            if has_sstc() {
+                core::arch::asm!("csrr {}, vstimecmp", out(reg) regs.vs_csrs.vstimecmp);
+            }
+        }
+        // Save off the trap information
+        regs.trap_csrs.scause = scause::read().bits();
+        regs.trap_csrs.stval = stval::read();
+        regs.trap_csrs.htval = htval::read();
+        regs.trap_csrs.htinst = htinst::read();
+
+        let scause = scause::read();
+        use scause::{Exception, Interrupt, Trap};
+        match scause.cause() {
+            Trap::Exception(Exception::VirtualSupervisorEnvCall) => {
+                let sbi_msg = SbiMessage::from_regs(regs.guest_regs.gprs.a_regs()).ok();
+                VmExitInfo::Ecall(sbi_msg)
+            }
+            Trap::Interrupt(Interrupt::SupervisorTimer) => VmExitInfo::TimerInterruptEmulation,
+            Trap::Interrupt(Interrupt::SupervisorExternal) => {
+                VmExitInfo::ExternalInterruptEmulation
+            }
+            Trap::Interrupt(Interrupt::SupervisorSoft) => {
+                // A software interrupt for the host, reported to the embedder through the exit.
+                CSR.sip
+                    .read_and_clear_bits(traps::interrupt::SUPERVISOR_SOFT);
+                VmExitInfo::HostInterruot(mcause::Interrupt::SupervisorSoft)
+            }
+            Trap::Exception(Exception::InstructionGuestPageFault)
+            | Trap::Exception(Exception::LoadGuestPageFault)
+            | Trap::Exception(Exception::StoreGuestPageFault) => {
+                let fault_addr = regs.trap_csrs.htval << 2 | regs.trap_csrs.stval & 0x3;
+                let access = match scause.cause() {
+                    Trap::Exception(Exception::InstructionGuestPageFault) => GuestAccessType::Fetch,
+                    Trap::Exception(Exception::LoadGuestPageFault) => GuestAccessType::Load,
+                    _ => GuestAccessType::Store,
+                };
+                VmExitInfo::PageFault {
+                    fault_addr,
+                    // Note that this address is not necessarily guest virtual as the guest may or
+                    // may not have 1st-stage translation enabled in VSATP. We still use GuestVirtAddr
+                    // here though to distinguish it from addresses (e.g. in HTVAL, or passed via a
+                    // TEECALL) which are exclusively guest-physical. Furthermore we only access guest
+                    // instructions via the HLVX instruction, which will take the VSATP translation
+                    // mode into account.
+                    falut_pc: regs.guest_regs.sepc,
+                    fault_vaddr: regs.trap_csrs.stval,
+                    inst: regs.trap_csrs.htinst as u32,
+                    access,
+                    priv_level: PrivilegeLevel::from_hstatus(regs.guest_regs.hstatus),
+                }
+            }
+            Trap::Exception(Exception::VirtualInstruction) => VmExitInfo::VirtualInstruction {
+                fault_pc: regs.guest_regs.sepc,
+                inst: regs.trap_csrs.stval as u32,
+                priv_level: PrivilegeLevel::from_hstatus(regs.guest_regs.hstatus),
+            },
+            Trap::Exception(_) => VmExitInfo::GuestException {
+                cause: 1 << regs.trap_csrs.scause,
+                tval: regs.trap_csrs.stval,
+            },
+            _ => {
+                panic!(
+                    "Unhandled trap: {:?}, sepc: {:#x}, stval: {:#x}",
+                    scause.cause(),
+                    regs.guest_regs.sepc,
+                    regs.trap_csrs.stval
+                );
+            }
+        }
+    }
+
+    /// Gets one of the vCPU's general purpose registers.
+    pub fn get_gpr(&self, index: GprIndex) -> usize {
+        self.regs.guest_regs.gprs.reg(index)
+    }
+
+    /// Set one of the vCPU's general purpose register.
+    pub fn set_gpr(&mut self, index: GprIndex, val: usize) {
+        self.regs.guest_regs.gprs.set_reg(index, val);
+    }
+
+    /// Advance guest pc by `instr_len` bytes
+    pub fn advance_pc(&mut self, instr_len: usize) {
+        self.regs.guest_regs.sepc += instr_len
+    }
+
+    /// Set guest pc to `pc`.
+    pub fn set_pc(&mut self, pc: GuestPhysAddr) {
+        self.regs.guest_regs.sepc = pc
+    }
+
+    /// Resets the vCPU to start executing at `entry` in VS-mode, as required by SBI HSM
+    /// `hart_start`: a0 holds the hart id, a1 holds `opaque`, `satp` is zero and interrupts are
+    /// disabled. The vCPU must not be loaded on a hart, its saved VS-level CSRs are reset.
+    pub fn start(&mut self, entry: GuestPhysAddr, opaque: usize) {
+        debug_assert!(self.status != VmCpuStatus::Running);
+        self.set_start_regs(entry, opaque);
+        let mut vsstatus =
+            LocalRegisterCopy::<usize, sstatus_defs::Register>::new(self.regs.vs_csrs.vsstatus);
+        vsstatus.modify(sstatus_defs::sie::CLEAR);
+        self.regs.vs_csrs.vsstatus = vsstatus.get();
+        self.regs.vs_csrs.vsatp = 0;
+    }
+
+    /// Like `start`, for the vCPU running on this hart, e.g. resuming from a non-retentive
+    /// suspend. The VS-level CSRs are live, so they are reset on the hart, and saved as such once
+    /// the vCPU is put.
+    pub(crate) fn start_loaded(&mut self, entry: GuestPhysAddr, opaque: usize) {
+        debug_assert!(self.status == VmCpuStatus::Running);
+        self.set_start_regs(entry, opaque);
+        let live_vsstatus: usize;
+        unsafe { core::arch::asm!("csrr {}, vsstatus", out(reg) live_vsstatus) };
+        let mut vsstatus = LocalRegisterCopy::<usize, sstatus_defs::Register>::new(live_vsstatus);
+        vsstatus.modify(sstatus_defs::sie::CLEAR);
+        unsafe {
+            core::arch::asm!(
+                "csrw vsstatus, {vsstatus}",
+                "csrw vsatp, zero",
+                vsstatus = in(reg) vsstatus.get(),
+            );
+        }
+    }
+
+    /// Returns the offset of the guest's time base from the host's, i.e. `htimedelta`.
+    pub fn time_delta(&self) -> usize {
+        self.regs.vs_csrs.htimedelta
+    }
+
+    /// Sets the offset of the guest's time base from the host's, i.e. `htimedelta`.
+    pub fn set_time_delta(&mut self, delta: usize) {
+        self.regs.vs_csrs.htimedelta = delta;
+    }
+
+    /// Returns the guest's `vsatp` as of the last time the vCPU was put.
+    pub(crate) fn vsatp(&self) -> usize {
+        self.regs.vs_csrs.vsatp
+    }
+
+    /// Returns the IMSIC guest interrupt file assigned to the vCPU, if any.
+    pub fn guest_interrupt_file(&self) -> Option<GuestInterruptFile> {
+        self.guest_file
+    }
+
+    /// Delivers the guest's external interrupts through the guest interrupt file `file` of the
+    /// hart the vCPU runs on, by selecting it in `hstatus.VGEIN`.
+    pub(crate) fn set_guest_interrupt_file(&mut self, file: GuestInterruptFile) {
+        let mut hstatus =
+            LocalRegisterCopy::<usize, hstatus::Register>::new(self.regs.guest_regs.hstatus);
+        hstatus.modify(hstatus::vgein.val(file.guest_index));
+        self.regs.guest_regs.hstatus = hstatus.get();
+        self.guest_file = Some(file);
+    }
+
+    /// Programs the guest's `vstimecmp` with `deadline`, in the guest's time base. Only
+    /// effective with Sstc.
+    pub fn set_timer(&mut self, deadline: u64) {
+        self.regs.vs_csrs.vstimecmp = deadline as usize;
+    }
+
+    /// Queues the virtual interrupts in `irqs` (`hvip` bits) to be asserted the next time the
+    /// vCPU runs.
+    pub fn inject_interrupt(&mut self, irqs: usize) {
+        self.pending_irqs |= irqs;
+    }
+
+    /// Returns the guest's `scounteren`, which controls VU-mode access to the counter CSRs.
+    pub fn guest_scounteren(&self) -> usize {
+        self.regs.guest_regs.scounteren
+    }
+
+    /// Asserts the virtual interrupts queued for the vCPU in `hvip`. The vCPU must be loaded on
+    /// this hart.
+    pub(crate) fn flush_pending_irqs(&mut self) {
+        let pending_irqs = core::mem::take(&mut self.pending_irqs);
+        if pending_irqs != 0 {
+            CSR.hvip.read_and_set_bits(pending_irqs);
+        }
+    }
+
+    /// Returns whether the vCPU, loaded on this hart, has a virtual interrupt pending that it
+    /// enabled in `vsie`, i.e. one that ends its `wfi` or wakes it from a suspend. Unlike `hvip`,
+    /// `hip` also holds the Sstc timer. Outside the guest `hstatus.VGEIN` is the host's, so the
+    /// guest interrupt file's interrupt is read from `hgeip` instead.
+    pub(crate) fn has_wakeup_irq(&mut self) -> bool {
+        self.flush_pending_irqs();
+        let hip: usize;
+        let vsie: usize;
+        let hgeip: usize;
+        unsafe {
+            core::arch::asm!(
+                "csrr {hip}, hip",
+                "csrr {vsie}, vsie",
+                "csrr {hgeip}, hgeip",
+                hip = out(reg) hip,
+                vsie = out(reg) vsie,
+                hgeip = out(reg) hgeip,
+            );
+        }
+        wakeup_irq_pending(hip, vsie, hgeip, self.guest_file)
+    }
+
+    /// Gets the hart ID of the physical CPU this vCPU runs or last ran on, or `None` if it never
+    /// ran.
+    pub fn pcpu_id(&self) -> Option<usize> {
+        self.pcpu_id
+    }
+
+    /// Sets the hart ID of the physical CPU this vCPU is about to run on. When the vCPU moves to
+    /// another hart, that hart may hold stale TLB entries under the VM's VMID, which get flushed.
+    /// This doesn't cover another vCPU of the VM running on the same hart in between: `VM::run`
+    /// queues a VS-stage flush for that.
+    pub fn set_pcpu_id(&mut self, pcpu_id: usize) {
+        if self.pcpu_id != Some(pcpu_id) {
+            self.pending_fences |= PENDING_FENCE_I | PENDING_HFENCE_VVMA | PENDING_HFENCE_GVMA;
+        }
+        self.pcpu_id = Some(pcpu_id);
+    }
+
+    /// Queues the fences `fences` (`PENDING_*`) to be done the next time the vCPU runs, on the
+    /// hart it runs on and under the VM's VMID.
+    pub(crate) fn queue_fences(&mut self, fences: usize) {
+        self.pending_fences |= fences;
+    }
+
+    // Does the queued fences on this hart, once the vCPU's `hgatp` is loaded.
+    fn run_pending_fences(&mut self) {
+        let fences = core::mem::take(&mut self.pending_fences);
+        unsafe {
+            if fences & PENDING_HFENCE_GVMA != 0 {
+                let vmid = (self.regs.virtual_hs_csrs.hgatp & HGATP_VMID_MASK) >> HGATP_VMID_SHIFT;
+                core::arch::riscv64::hfence_gvma_vmid(vmid);
+            }
+            if fences & PENDING_HFENCE_VVMA != 0 {
+                core::arch::riscv64::hfence_vvma_all();
+            }
+            if fences & PENDING_FENCE_I != 0 {
+                core::arch::asm!("fence.i");
+            }
+        }
+    }
+
+    /// Returns the vCPU's virtual PMU.
+    pub(crate) fn pmu(&mut self) -> &mut VirtualPmu {
+        &mut self.pmu
+    }
+
+    /// Returns the vCPU's timer, emulated over the host timer without Sstc.
+    pub(crate) fn timer(&mut self) -> &mut VCpuTimer {
+        &mut self.timer
+    }
+
+    /// Gets the vCPU's run state.
+    pub fn status(&self) -> VmCpuStatus {
+        self.status
+    }
+
+    /// Sets the vCPU's run state.
+    pub fn set_status(&mut self, status: VmCpuStatus) {
+        self.status = status;
+    }
+
+    /// Gets the vCPU's id.
+    pub fn vcpu_id(&self) -> usize {
+        self.vcpu_id
+    }
+
+    /// Gets the vCPU's registers.
+    pub fn regs(&mut self) -> &mut VmCpuRegisters {
+        &mut self.regs
+    }
+}
+
+// Private methods implements
+impl<H: HyperCraftHal> VCpu<H> {
+    /// Sets the registers restored on entry for the vCPU to start executing at `entry` in
+    /// VS-mode, with its hart id in a0 and `opaque` in a1.
+    fn set_start_regs(&mut self, entry: GuestPhysAddr, opaque: usize) {
+        let mut sstatus =
+            LocalRegisterCopy::<usize, sstatus_defs::Register>::new(self.regs.guest_regs.sstatus);
+        sstatus.modify(sstatus_defs::spp::Supervisor + sstatus_defs::sie::CLEAR);
+        self.regs.guest_regs.sstatus = sstatus.get();
+
+        self.regs
+            .guest_regs
+            .gprs
+            .set_reg(GprIndex::A0, self.vcpu_id);
+        self.regs.guest_regs.gprs.set_reg(GprIndex::A1, opaque);
+        self.regs.guest_regs.sepc = entry;
+    }
+
+    /// Delivers the exception `exception` (one of `traps::exception`) with trap value `tval` to
+    /// the vCPU as if it had been taken in VS-mode, setting its register state to enter the
+    /// guest's trap handler the next time it is run. The vCPU must be loaded on this hart, since
+    /// the VS-level CSRs are live.
+    pub fn inject_exception(&mut self, exception: usize, tval: usize) {
+        let mut guest_sstatus =
+            LocalRegisterCopy::<usize, sstatus_defs::Register>::new(self.regs.guest_regs.sstatus);
+        let mut vsstatus = LocalRegisterCopy::<usize, sstatus_defs::Register>::new(0);
+        let vstvec: usize;
+        unsafe {
+            core::arch::asm!(
+                "csrr {vsstatus}, vsstatus",
+                "csrr {vstvec}, vstvec",
+                vsstatus = out(reg) *vsstatus.get_mut(),
+                vstvec = out(reg) vstvec,
+            );
+        }
+
+        // The trap is taken from the privilege level the guest was running at into VS-mode, with
+        // interrupts disabled, just like the hardware would do.
+        vsstatus.modify(sstatus_defs::spp.val(guest_sstatus.read(sstatus_defs::spp)));
+        vsstatus.modify(sstatus_defs::spie.val(vsstatus.read(sstatus_defs::sie)));
+        vsstatus.modify(sstatus_defs::sie::CLEAR);
+        guest_sstatus.modify(sstatus_defs::spp::Supervisor);
+
+        unsafe {
+            core::arch::asm!(
+                "csrw vsstatus, {vsstatus}",
+                "csrw vsepc, {vsepc}",
+                "csrw vscause, {vscause}",
+                "csrw vstval, {vstval}",
+                vsstatus = in(reg) vsstatus.get(),
+                vsepc = in(reg) self.regs.guest_regs.sepc,
+                vscause = in(reg) exception.trailing_zeros() as usize,
+                vstval = in(reg) tval,
+            );
+        }
+        self.regs.guest_regs.sstatus = guest_sstatus.get();
+        // Synchronous exceptions always go to the base address, even in vectored mode.
+        self.regs.guest_regs.sepc = vstvec & !0b11;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEIE: usize = traps::interrupt::SUPERVISOR_EXTERNAL;
+    const STIE: usize = traps::interrupt::SUPERVISOR_TIMER;
+
+    #[test]
+    fn guest_file_interrupt_wakes() {
+        let file = GuestInterruptFile {
+            hart_id: 0,
+            guest_index: 2,
+            host_paddr: 0x2800_0000,
+            host_vaddr: 0x2800_0000,
+        };
+        // With the host's VGEIN, `hip.VSEIP` stays clear while the guest file has an interrupt.
+        assert!(wakeup_irq_pending(0, SEIE, 1 << 2, Some(file)));
+        // Only the vCPU's own file counts, and only with `vsie.SEIE`.
+        assert!(!wakeup_irq_pending(0, SEIE, 1 << 3, Some(file)));
+        assert!(!wakeup_irq_pending(0, STIE, 1 << 2, Some(file)));
+        assert!(!wakeup_irq_pending(0, SEIE, 1 << 2, None));
+    }
+
+    #[test]
+    fn vs_interrupts_wake_when_enabled() {
+        let vstip = traps::interrupt::VIRTUAL_SUPERVISOR_TIMER;
+        assert!(wakeup_irq_pending(vstip, STIE, 0, None));
+        assert!(!wakeup_irq_pending(vstip, SEIE, 0, None));
+        // The host's own interrupts never wake the vCPU.
+        assert!(!wakeup_irq_pending(STIE | SEIE, STIE | SEIE, 0, None));
+    }
+}
@@ -0,0 +1,119 @@
+use super::regs::GprIndex;
+use crate::{HyperError, HyperResult};
+
+const OPCODE_SYSTEM: u32 = 0b111_0011;
+
+const INST_WFI: u32 = 0x1050_0073;
+const INST_SFENCE_W_INVAL: u32 = 0x1800_0073;
+const INST_SFENCE_INVAL_IR: u32 = 0x1810_0073;
+
+const FUNCT7_SFENCE_VMA: u32 = 0b000_1001;
+const FUNCT7_SINVAL_VMA: u32 = 0b000_1011;
+
+/// The CSR the counter CSRs (`cycle`, `time`, `instret`, `hpmcounter3-31`) start at.
+pub const CSR_CYCLE: u16 = 0xc00;
+/// The `time` CSR.
+pub const CSR_TIME: u16 = 0xc01;
+/// The `instret` CSR.
+pub const CSR_INSTRET: u16 = 0xc02;
+/// The `hpmcounter31` CSR, the last of the counter CSRs.
+pub const CSR_HPMCOUNTER31: u16 = 0xc1f;
+/// The `satp` CSR, which traps when `hstatus.VTVM` is set.
+pub const CSR_SATP: u16 = 0x180;
+/// The `sireg` CSR, which traps when the vCPU has no guest interrupt file.
+pub const CSR_SIREG: u16 = 0x151;
+/// The `stopei` CSR, which traps when the vCPU has no guest interrupt file.
+pub const CSR_STOPEI: u16 = 0x15c;
+
+/// The read-modify-write operation of a CSR instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsrOp {
+    /// `csrrw(i)`: writes the operand.
+    Write,
+    /// `csrrs(i)`: sets the bits in the operand.
+    Set,
+    /// `csrrc(i)`: clears the bits in the operand.
+    Clear,
+}
+
+/// The source operand of a CSR instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsrOperand {
+    /// The value of a register, for `csrrw/csrrs/csrrc`.
+    Reg(GprIndex),
+    /// A 5-bit zero-extended immediate, for `csrrwi/csrrsi/csrrci`.
+    Imm(usize),
+}
+
+/// An instruction that may raise a virtual-instruction exception when executed by the guest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VirtualInstruction {
+    /// `wfi`.
+    Wfi,
+    /// `sfence.vma` or `sinval.vma`, flushing the address in `vaddr` (all if `x0`) for the ASID in
+    /// `asid` (all if `x0`).
+    SfenceVma {
+        /// The register holding the virtual address.
+        vaddr: GprIndex,
+        /// The register holding the ASID.
+        asid: GprIndex,
+    },
+    /// `sfence.w.inval` or `sfence.inval.ir`, which order `sinval.vma` against other accesses.
+    SfenceInval,
+    /// A CSR access.
+    Csr {
+        /// The accessed CSR.
+        csr: u16,
+        /// The operation.
+        op: CsrOp,
+        /// The register receiving the old value.
+        rd: GprIndex,
+        /// The source operand.
+        src: CsrOperand,
+    },
+}
+
+impl VirtualInstruction {
+    /// Decodes the 32-bit instruction `inst`. Returns `InvalidInstruction` for anything that
+    /// can't raise a virtual-instruction exception or that the hypervisor doesn't emulate.
+    pub fn decode(inst: u32) -> HyperResult<Self> {
+        if inst & 0x7f != OPCODE_SYSTEM {
+            return Err(HyperError::InvalidInstruction);
+        }
+        let rd = GprIndex::from_raw((inst >> 7) & 0x1f).unwrap();
+        let rs1 = (inst >> 15) & 0x1f;
+        let rs2 = GprIndex::from_raw((inst >> 20) & 0x1f).unwrap();
+        let op = match (inst >> 12) & 0b111 {
+            0b000 => {
+                return match inst {
+                    INST_WFI => Ok(Self::Wfi),
+                    INST_SFENCE_W_INVAL | INST_SFENCE_INVAL_IR => Ok(Self::SfenceInval),
+                    _ if rd == GprIndex::Zero
+                        && matches!(inst >> 25, FUNCT7_SFENCE_VMA | FUNCT7_SINVAL_VMA) =>
+                    {
+                        Ok(Self::SfenceVma {
+                            vaddr: GprIndex::from_raw(rs1).unwrap(),
+                            asid: rs2,
+                        })
+                    }
+                    _ => Err(HyperError::InvalidInstruction),
+                };
+            }
+            0b001 | 0b101 => CsrOp::Write,
+            0b010 | 0b110 => CsrOp::Set,
+            0b011 | 0b111 => CsrOp::Clear,
+            _ => return Err(HyperError::InvalidInstruction),
+        };
+        let src = if inst & (1 << 14) != 0 {
+            CsrOperand::Imm(rs1 as usize)
+        } else {
+            CsrOperand::Reg(GprIndex::from_raw(rs1).unwrap())
+        };
+        Ok(Self::Csr {
+            csr: (inst >> 20) as u16,
+            op,
+            rd,
+            src,
+        })
+    }
+}
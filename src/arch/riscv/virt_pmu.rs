@@ -0,0 +1,475 @@
+use crate::GuestPhysAddr;
+
+use super::sbi::{
+    SBI_ERR_ALREADY_STARTED, SBI_ERR_ALREADY_STOPPED, SBI_ERR_INAVLID_PARAM, SBI_ERR_NOT_SUPPORTED,
+};
+
+/// The maximum number of counters the SBI PMU extension can address.
+pub const MAX_COUNTERS: usize = 64;
+
+/// The `hcounteren` bits of `cycle`, `time` and `instret`, which guests can always read.
+pub const FIXED_COUNTERS: usize = 0b111;
+
+/// `config_flags` of `counter_config_matching`.
+pub mod config_flags {
+    /// Skip the counter matching and reuse the already configured counter.
+    pub const SKIP_MATCH: usize = 1 << 0;
+    /// Clear the counter value.
+    pub const CLEAR_VALUE: usize = 1 << 1;
+    /// Start the counter right away.
+    pub const AUTO_START: usize = 1 << 2;
+    /// Don't count events in VU-mode.
+    pub const SET_VUINH: usize = 1 << 3;
+    /// Don't count events in VS-mode.
+    pub const SET_VSINH: usize = 1 << 4;
+    /// Don't count events in U-mode.
+    pub const SET_UINH: usize = 1 << 5;
+    /// Don't count events in S-mode.
+    pub const SET_SINH: usize = 1 << 6;
+    /// Don't count events in M-mode.
+    pub const SET_MINH: usize = 1 << 7;
+}
+
+/// `start_flags` of `counter_start`.
+pub mod start_flags {
+    /// Set the counter to `initial_value` before starting it.
+    pub const SET_INIT_VALUE: usize = 1 << 0;
+    /// Set the counter to its value in the snapshot shared memory before starting it.
+    pub const INIT_SNAPSHOT: usize = 1 << 1;
+}
+
+/// `stop_flags` of `counter_stop`.
+pub mod stop_flags {
+    /// Release the counter.
+    pub const RESET: usize = 1 << 0;
+    /// Save the counter value to the snapshot shared memory.
+    pub const TAKE_SNAPSHOT: usize = 1 << 1;
+}
+
+/// Firmware events the hypervisor counts on behalf of the guest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum FirmwareEvent {
+    /// An access fault on a load was delivered.
+    AccessLoad = 2,
+    /// An access fault on a store was delivered.
+    AccessStore = 3,
+    /// An illegal instruction exception was delivered.
+    IllegalInsn = 4,
+    /// `set_timer` was called.
+    SetTimer = 5,
+    /// An IPI was sent.
+    IpiSent = 6,
+    /// A remote `fence.i` was requested.
+    FenceISent = 8,
+    /// A remote `sfence.vma` was requested.
+    SFenceVmaSent = 10,
+    /// A remote `sfence.vma` with ASID was requested.
+    SFenceVmaAsidSent = 12,
+}
+
+/// The event type of firmware events in `event_idx`.
+const EVENT_TYPE_FIRMWARE: usize = 0xf;
+
+/// Bit 63 of the counter info marks firmware counters.
+const COUNTER_INFO_FIRMWARE: usize = 1 << 63;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum CounterKind {
+    #[default]
+    Unused,
+    Hardware,
+    Firmware,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Counter {
+    kind: CounterKind,
+    // The guest's view of the configuration, reapplied when the vCPU moves back onto a hart.
+    event_idx: usize,
+    event_data: u64,
+    config_flags: usize,
+    started: bool,
+    // The value of a firmware counter, or of a hardware counter while it isn't loaded.
+    value: u64,
+    // Whether the hardware counter must be loaded with `value` before it is started again.
+    stale: bool,
+}
+
+/// The PMU of a vCPU. Hardware counters are borrowed from the host PMU through SBI while the vCPU
+/// runs and restricted to counting in VS/VU-mode; firmware counters are emulated.
+///
+/// Counter indices are the host's, so the guest sees the host's counter info and CSRs, but a
+/// vCPU can only operate on the counters it configured itself.
+pub struct VirtualPmu {
+    // The host's counter info, probed on first use.
+    host_info: Option<([usize; MAX_COUNTERS], usize)>,
+    counters: [Counter; MAX_COUNTERS],
+    // The guest physical address of the snapshot shared memory.
+    snapshot_shmem: Option<GuestPhysAddr>,
+}
+
+impl Default for VirtualPmu {
+    fn default() -> Self {
+        Self {
+            host_info: None,
+            counters: [Counter::default(); MAX_COUNTERS],
+            snapshot_shmem: None,
+        }
+    }
+}
+
+impl VirtualPmu {
+    /// Creates a PMU without any configured counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the host's counter info and number of counters.
+    fn host_info(&mut self) -> &([usize; MAX_COUNTERS], usize) {
+        self.host_info.get_or_insert_with(|| {
+            let mut info = [0; MAX_COUNTERS];
+            let mut num_counters = 0;
+            if sbi_rt::probe_extension(sbi_spec::pmu::EID_PMU).raw != 0 {
+                num_counters = sbi_rt::pmu_num_counters().min(MAX_COUNTERS);
+                for (idx, info) in info.iter_mut().enumerate().take(num_counters) {
+                    *info = sbi_rt::pmu_counter_get_info(idx).value;
+                }
+            }
+            (info, num_counters)
+        })
+    }
+
+    /// Returns the number of counters.
+    pub fn num_counters(&mut self) -> usize {
+        self.host_info().1
+    }
+
+    /// Returns the info of counter `idx`.
+    pub fn counter_info(&mut self, idx: usize) -> Result<usize, isize> {
+        let (info, num_counters) = self.host_info();
+        if idx >= *num_counters {
+            return Err(SBI_ERR_INAVLID_PARAM);
+        }
+        Ok(info[idx])
+    }
+
+    /// Returns the guest physical address of the snapshot shared memory, if set.
+    pub fn snapshot_shmem(&self) -> Option<GuestPhysAddr> {
+        self.snapshot_shmem
+    }
+
+    /// Sets the guest physical address of the snapshot shared memory, or disables it.
+    pub fn set_snapshot_shmem(&mut self, shmem: Option<GuestPhysAddr>) {
+        self.snapshot_shmem = shmem;
+    }
+
+    /// Returns the counters selected by `base` and `mask`, or an error if any is out of range.
+    fn selected(&mut self, base: usize, mask: usize) -> Result<impl Iterator<Item = usize>, isize> {
+        let num_counters = self.num_counters();
+        let selected = (0..usize::BITS as usize)
+            .filter(move |bit| mask & (1 << bit) != 0)
+            .map(move |bit| base.wrapping_add(bit));
+        if selected.clone().any(|idx| idx >= num_counters) {
+            return Err(SBI_ERR_INAVLID_PARAM);
+        }
+        Ok(selected)
+    }
+
+    /// Finds and configures a counter among `base` and `mask` able to count `event_idx`,
+    /// returning its index. A hardware counter is picked by the host among the ones the vCPU
+    /// doesn't use yet, as only the host knows which of them can count the event and are free on
+    /// this hart.
+    pub fn config_matching(
+        &mut self,
+        base: usize,
+        mask: usize,
+        flags: usize,
+        event_idx: usize,
+        event_data: u64,
+    ) -> Result<usize, isize> {
+        use config_flags::*;
+
+        let is_firmware = (event_idx >> 16) & 0xf == EVENT_TYPE_FIRMWARE;
+        let skip_match = flags & SKIP_MATCH != 0;
+        let info = self.host_info().0;
+        let mut candidates = self.selected(base, mask)?.filter(|&idx| {
+            let counter = &self.counters[idx];
+            if skip_match {
+                counter.kind != CounterKind::Unused
+            } else {
+                counter.kind == CounterKind::Unused
+                    && (info[idx] & COUNTER_INFO_FIRMWARE != 0) == is_firmware
+            }
+        });
+        let idx = if skip_match || is_firmware {
+            candidates.next().ok_or(SBI_ERR_NOT_SUPPORTED)?
+        } else {
+            let host_mask = candidates.fold(0, |mask, idx| mask | 1 << idx.wrapping_sub(base));
+            if host_mask == 0 {
+                return Err(SBI_ERR_NOT_SUPPORTED);
+            }
+            let ret = sbi_rt::pmu_counter_config_matching(
+                base,
+                host_mask,
+                host_config_flags(flags),
+                event_idx,
+                event_data,
+            );
+            if ret.error != 0 {
+                return Err(ret.error as isize);
+            }
+            let bit = ret.value.wrapping_sub(base);
+            if bit >= usize::BITS as usize || host_mask & (1 << bit) == 0 {
+                // Not one of the candidates, give it back.
+                sbi_rt::pmu_counter_stop(ret.value, 1, stop_flags::RESET);
+                return Err(SBI_ERR_NOT_SUPPORTED);
+            }
+            ret.value
+        };
+
+        let (event_idx, event_data) = if skip_match {
+            (self.counters[idx].event_idx, self.counters[idx].event_data)
+        } else {
+            (event_idx, event_data)
+        };
+        let counter = &mut self.counters[idx];
+        let is_firmware = if skip_match {
+            counter.kind == CounterKind::Firmware
+        } else {
+            is_firmware
+        };
+        if is_firmware {
+            counter.kind = CounterKind::Firmware;
+            if flags & CLEAR_VALUE != 0 {
+                counter.value = 0;
+            }
+        } else {
+            if skip_match {
+                let ret = sbi_rt::pmu_counter_config_matching(
+                    idx,
+                    1,
+                    host_config_flags(flags),
+                    event_idx,
+                    event_data,
+                );
+                if ret.error != 0 {
+                    return Err(ret.error as isize);
+                }
+            }
+            counter.kind = CounterKind::Hardware;
+            counter.stale = false;
+        }
+        counter.event_idx = event_idx;
+        counter.event_data = event_data;
+        counter.config_flags = flags & !(SKIP_MATCH | CLEAR_VALUE | AUTO_START);
+        counter.started = flags & AUTO_START != 0;
+        Ok(idx)
+    }
+
+    /// Starts the counters selected by `base` and `mask`. With `SET_INIT_VALUE` they start at
+    /// `initial_value`, with `INIT_SNAPSHOT` at their value in `snapshot`.
+    pub fn start(
+        &mut self,
+        base: usize,
+        mask: usize,
+        flags: usize,
+        initial_value: u64,
+        snapshot: Option<&[u64; MAX_COUNTERS]>,
+    ) -> Result<(), isize> {
+        for idx in self.selected(base, mask)? {
+            let counter = &mut self.counters[idx];
+            if counter.kind == CounterKind::Unused {
+                return Err(SBI_ERR_INAVLID_PARAM);
+            }
+            if counter.started {
+                return Err(SBI_ERR_ALREADY_STARTED);
+            }
+            let initial_value = match snapshot {
+                Some(snapshot) if flags & start_flags::INIT_SNAPSHOT != 0 => Some(snapshot[idx]),
+                _ if flags & start_flags::SET_INIT_VALUE != 0 => Some(initial_value),
+                _ if counter.stale => Some(counter.value),
+                _ => None,
+            };
+            if counter.kind == CounterKind::Hardware {
+                let ret = match initial_value {
+                    Some(value) => {
+                        sbi_rt::pmu_counter_start(idx, 1, start_flags::SET_INIT_VALUE, value)
+                    }
+                    None => sbi_rt::pmu_counter_start(idx, 1, 0usize, 0),
+                };
+                if ret.error != 0 {
+                    return Err(ret.error as isize);
+                }
+                counter.stale = false;
+            } else if let Some(value) = initial_value {
+                counter.value = value;
+            }
+            counter.started = true;
+        }
+        Ok(())
+    }
+
+    /// Stops the counters selected by `base` and `mask`, releasing them with `RESET`. With
+    /// `TAKE_SNAPSHOT` their values are saved to `snapshot`.
+    pub fn stop(
+        &mut self,
+        base: usize,
+        mask: usize,
+        flags: usize,
+        mut snapshot: Option<&mut [u64; MAX_COUNTERS]>,
+    ) -> Result<(), isize> {
+        let info = self.host_info().0;
+        for idx in self.selected(base, mask)? {
+            let counter = &mut self.counters[idx];
+            if counter.kind == CounterKind::Unused {
+                return Err(SBI_ERR_INAVLID_PARAM);
+            }
+            if !counter.started && flags & stop_flags::RESET == 0 {
+                return Err(SBI_ERR_ALREADY_STOPPED);
+            }
+            if counter.kind == CounterKind::Hardware {
+                if counter.started {
+                    counter.value = read_counter_csr(info[idx] & 0xfff).unwrap_or(0) as u64;
+                }
+                let ret = sbi_rt::pmu_counter_stop(idx, 1, flags & stop_flags::RESET);
+                if ret.error != 0 && counter.started {
+                    return Err(ret.error as isize);
+                }
+            }
+            if let Some(snapshot) = snapshot.as_deref_mut() {
+                if flags & stop_flags::TAKE_SNAPSHOT != 0 {
+                    snapshot[idx] = counter.value;
+                }
+            }
+            counter.started = false;
+            if flags & stop_flags::RESET != 0 {
+                *counter = Counter::default();
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads firmware counter `idx`.
+    pub fn fw_read(&mut self, idx: usize) -> Result<u64, isize> {
+        match self.counters.get(idx) {
+            Some(counter) if counter.kind == CounterKind::Firmware => Ok(counter.value),
+            _ => Err(SBI_ERR_INAVLID_PARAM),
+        }
+    }
+
+    /// Counts an occurrence of the firmware event `event` on the started counters
+    /// configured for it.
+    pub fn record_fw_event(&mut self, event: FirmwareEvent) {
+        let event_idx = EVENT_TYPE_FIRMWARE << 16 | event as usize;
+        for counter in self.counters.iter_mut() {
+            if counter.kind == CounterKind::Firmware
+                && counter.started
+                && counter.event_idx == event_idx
+            {
+                counter.value = counter.value.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Returns the `hcounteren` bits of the hardware counters the vCPU may read directly.
+    pub fn counter_enable_mask(&mut self) -> usize {
+        let info = self.host_info().0;
+        self.counters
+            .iter()
+            .enumerate()
+            .filter(|(_, counter)| counter.kind == CounterKind::Hardware)
+            .map(|(idx, _)| 1 << ((info[idx] & 0xfff).wrapping_sub(CSR_CYCLE) & 0x1f))
+            .fold(0, |mask, bit| mask | bit)
+    }
+
+    /// Releases all counters and disables the snapshot shared memory. The vCPU must not be loaded
+    /// on a hart, so `save` already gave its hardware counters back to the host.
+    pub fn reset(&mut self) {
+        *self = Self {
+            host_info: self.host_info,
+            ..Self::default()
+        };
+    }
+
+    /// Stops the vCPU's hardware counters, saves their values and releases them on this hart, to
+    /// be called when the vCPU leaves it. The vCPU doesn't hold any of the host's counters while
+    /// it's off a hart.
+    pub fn save(&mut self) {
+        let Some((info, _)) = self.host_info else {
+            return;
+        };
+        for (idx, counter) in self.counters.iter_mut().enumerate() {
+            if counter.kind != CounterKind::Hardware {
+                continue;
+            }
+            if counter.started {
+                counter.value = read_counter_csr(info[idx] & 0xfff).unwrap_or(0) as u64;
+            }
+            sbi_rt::pmu_counter_stop(idx, 1, stop_flags::RESET);
+            counter.stale = true;
+        }
+    }
+
+    /// Configures and restarts the vCPU's hardware counters on this hart, to be called when the
+    /// vCPU enters it.
+    pub fn restore(&mut self) {
+        for (idx, counter) in self.counters.iter_mut().enumerate() {
+            if counter.kind != CounterKind::Hardware || !counter.stale {
+                continue;
+            }
+            sbi_rt::pmu_counter_config_matching(
+                idx,
+                1,
+                host_config_flags(counter.config_flags),
+                counter.event_idx,
+                counter.event_data,
+            );
+            if counter.started {
+                sbi_rt::pmu_counter_start(idx, 1, start_flags::SET_INIT_VALUE, counter.value);
+                counter.stale = false;
+            }
+        }
+    }
+}
+
+/// Translates the guest's `config_flags` to the host's: the guest's U/S-mode are VU/VS-mode, and
+/// nothing is counted outside the guest.
+fn host_config_flags(flags: usize) -> usize {
+    use config_flags::*;
+
+    let mut host_flags = flags & (SKIP_MATCH | CLEAR_VALUE | AUTO_START);
+    host_flags |= SET_UINH | SET_SINH | SET_MINH;
+    if flags & SET_UINH != 0 {
+        host_flags |= SET_VUINH;
+    }
+    if flags & SET_SINH != 0 {
+        host_flags |= SET_VSINH;
+    }
+    host_flags
+}
+
+const CSR_CYCLE: usize = 0xc00;
+
+/// Reads the counter CSR `csr`, one of `cycle`, `time`, `instret` or `hpmcounter3-31`.
+fn read_counter_csr(csr: usize) -> Option<usize> {
+    macro_rules! read_csr {
+        ($($csr:literal),*) => {
+            match csr {
+                $($csr => {
+                    let val: usize;
+                    unsafe {
+                        core::arch::asm!(concat!("csrr {}, ", stringify!($csr)), out(reg) val)
+                    };
+                    Some(val)
+                })*
+                _ => None,
+            }
+        };
+    }
+    read_csr!(
+        0xc00, 0xc01, 0xc02, 0xc03, 0xc04, 0xc05, 0xc06, 0xc07, 0xc08, 0xc09, 0xc0a, 0xc0b, 0xc0c,
+        0xc0d, 0xc0e, 0xc0f, 0xc10, 0xc11, 0xc12, 0xc13, 0xc14, 0xc15, 0xc16, 0xc17, 0xc18, 0xc19,
+        0xc1a, 0xc1b, 0xc1c, 0xc1d, 0xc1e, 0xc1f
+    )
+}
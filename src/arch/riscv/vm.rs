@@ -1,318 +1,1614 @@
-use core::panic;
-
-use super::{
-    devices::plic::{PlicState, MAX_CONTEXTS},
-    regs::GeneralPurposeRegisters,
-    sbi::PmuFunction,
-    sbi::{BaseFunction, RemoteFenceFunction},
-    traps,
-    vcpu::{self, VmCpuRegisters},
-    vm_pages::VmPages,
-    HyperCallMsg, RiscvCsrTrait, CSR,
-};
-use crate::{
-    arch::sbi::SBI_ERR_NOT_SUPPORTED, vcpus::VM_CPUS_MAX, GprIndex, GuestPageTableTrait,
-    GuestPhysAddr, GuestVirtAddr, HyperCraftHal, HyperError, HyperResult, VCpu, VmCpus, VmExitInfo,
-};
-use riscv_decode::Instruction;
-use sbi_rt::{pmu_counter_get_info, pmu_counter_stop};
-
-/// A VM that is being run.
-pub struct VM<H: HyperCraftHal, G: GuestPageTableTrait> {
-    vcpus: VmCpus<H>,
-    gpt: G,
-    vm_pages: VmPages,
-    plic: PlicState,
-}
-
-impl<H: HyperCraftHal, G: GuestPageTableTrait> VM<H, G> {
-    /// Create a new VM with `vcpus` vCPUs and `gpt` as the guest page table.
-    pub fn new(vcpus: VmCpus<H>, gpt: G) -> HyperResult<Self> {
-        Ok(Self {
-            vcpus,
-            gpt,
-            vm_pages: VmPages::default(),
-            plic: PlicState::new(0xC00_0000),
-        })
-    }
-
-    /// Initialize `VCpu` by `vcpu_id`.
-    pub fn init_vcpu(&mut self, vcpu_id: usize) {
-        let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
-        vcpu.init_page_map(self.gpt.token());
-    }
-
-    #[allow(unused_variables, deprecated)]
-    /// Run the host VM's vCPU with ID `vcpu_id`. Does not return.
-    pub fn run(&mut self, vcpu_id: usize) {
-        let mut vm_exit_info: VmExitInfo;
-        let mut gprs = GeneralPurposeRegisters::default();
-        loop {
-            let mut len = 4;
-            let mut advance_pc = false;
-            {
-                let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
-                vm_exit_info = vcpu.run();
-                vcpu.save_gprs(&mut gprs);
-            }
-
-            match vm_exit_info {
-                VmExitInfo::Ecall(sbi_msg) => {
-                    if let Some(sbi_msg) = sbi_msg {
-                        match sbi_msg {
-                            HyperCallMsg::Base(base) => {
-                                self.handle_base_function(base, &mut gprs).unwrap();
-                            }
-                            HyperCallMsg::GetChar => {
-                                let c = sbi_rt::legacy::console_getchar();
-                                gprs.set_reg(GprIndex::A1, c);
-                            }
-                            HyperCallMsg::PutChar(c) => {
-                                sbi_rt::legacy::console_putchar(c);
-                            }
-                            HyperCallMsg::SetTimer(timer) => {
-                                sbi_rt::set_timer(timer as u64);
-                                // Clear guest timer interrupt
-                                CSR.hvip.read_and_clear_bits(
-                                    traps::interrupt::VIRTUAL_SUPERVISOR_TIMER,
-                                );
-                                //  Enable host timer interrupt
-                                CSR.sie
-                                    .read_and_set_bits(traps::interrupt::SUPERVISOR_TIMER);
-                            }
-                            HyperCallMsg::Reset(_) => {
-                                sbi_rt::system_reset(sbi_rt::Shutdown, sbi_rt::SystemFailure);
-                            }
-                            HyperCallMsg::RemoteFence(rfnc) => {
-                                self.handle_rfnc_function(rfnc, &mut gprs).unwrap();
-                            }
-                            HyperCallMsg::PMU(pmu) => {
-                                self.handle_pmu_function(pmu, &mut gprs).unwrap();
-                            }
-                            _ => todo!(),
-                        }
-                        advance_pc = true;
-                    } else {
-                        panic!()
-                    }
-                }
-                VmExitInfo::PageFault {
-                    fault_addr,
-                    falut_pc,
-                    inst,
-                    priv_level,
-                } => match priv_level {
-                    super::vmexit::PrivilegeLevel::Supervisor => {
-                        match self.handle_page_fault(falut_pc, inst, fault_addr, &mut gprs) {
-                            Ok(inst_len) => {
-                                len = inst_len;
-                            }
-                            Err(err) => {
-                                panic!(
-                                    "Page fault at {:#x} addr@{:#x} with error {:?}",
-                                    falut_pc, fault_addr, err
-                                )
-                            }
-                        }
-                        advance_pc = true;
-                    }
-                    super::vmexit::PrivilegeLevel::User => {
-                        panic!("User page fault")
-                    }
-                },
-                VmExitInfo::TimerInterruptEmulation => {
-                    // debug!("timer irq emulation");
-                    // Enable guest timer interrupt
-                    CSR.hvip
-                        .read_and_set_bits(traps::interrupt::VIRTUAL_SUPERVISOR_TIMER);
-                    // Clear host timer interrupt
-                    CSR.sie
-                        .read_and_clear_bits(traps::interrupt::SUPERVISOR_TIMER);
-                }
-                VmExitInfo::ExternalInterruptEmulation => self.handle_irq(),
-                _ => {}
-            }
-
-            {
-                let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
-                vcpu.restore_gprs(&gprs);
-                if advance_pc {
-                    vcpu.advance_pc(len);
-                }
-            }
-        }
-    }
-}
-
-// Privaie methods implementation
-impl<H: HyperCraftHal, G: GuestPageTableTrait> VM<H, G> {
-    fn handle_page_fault(
-        &mut self,
-        inst_addr: GuestVirtAddr,
-        inst: u32,
-        fault_addr: GuestPhysAddr,
-        gprs: &mut GeneralPurposeRegisters,
-    ) -> HyperResult<usize> {
-        //  plic
-        if fault_addr >= self.plic.base() && fault_addr < self.plic.base() + 0x0400_0000 {
-            self.handle_plic(inst_addr, inst, fault_addr, gprs)
-        } else {
-            error!("inst_addr: {:#x}, fault_addr: {:#x}", inst_addr, fault_addr);
-            Err(HyperError::PageFault)
-        }
-    }
-
-    #[allow(clippy::needless_late_init)]
-    fn handle_plic(
-        &mut self,
-        inst_addr: GuestVirtAddr,
-        mut inst: u32,
-        fault_addr: GuestPhysAddr,
-        gprs: &mut GeneralPurposeRegisters,
-    ) -> HyperResult<usize> {
-        if inst == 0 {
-            // If hinst does not provide information about trap,
-            // we must read the instruction from guest's memory maunally.
-            inst = self.vm_pages.fetch_guest_instruction(inst_addr)?;
-        }
-        let i1 = inst as u16;
-        let len = riscv_decode::instruction_length(i1);
-        let inst = match len {
-            2 => i1 as u32,
-            4 => inst,
-            _ => unreachable!(),
-        };
-        // assert!(len == 4);
-        let decode_inst = riscv_decode::decode(inst).map_err(|_| HyperError::DecodeError)?;
-        match decode_inst {
-            Instruction::Sw(i) => {
-                let val = gprs.reg(GprIndex::from_raw(i.rs2()).unwrap()) as u32;
-                self.plic.write_u32(fault_addr, val)
-            }
-            Instruction::Lw(i) => {
-                let val = self.plic.read_u32(fault_addr);
-                gprs.set_reg(GprIndex::from_raw(i.rd()).unwrap(), val as usize)
-            }
-            _ => return Err(HyperError::InvalidInstruction),
-        }
-        Ok(len)
-    }
-
-    fn handle_irq(&mut self) {
-        let context_id = 1;
-        let claim_and_complete_addr = self.plic.base() + 0x0020_0004 + 0x1000 * context_id;
-        let irq = unsafe { core::ptr::read_volatile(claim_and_complete_addr as *const u32) };
-        assert!(irq != 0);
-        self.plic.claim_complete[context_id] = irq;
-
-        CSR.hvip
-            .read_and_set_bits(traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL);
-    }
-
-    fn handle_base_function(
-        &self,
-        base: BaseFunction,
-        gprs: &mut GeneralPurposeRegisters,
-    ) -> HyperResult<()> {
-        match base {
-            BaseFunction::GetSepcificationVersion => {
-                let version = sbi_rt::get_spec_version();
-                gprs.set_reg(GprIndex::A1, version.major() << 24 | version.minor());
-                debug!(
-                    "GetSepcificationVersion: {}",
-                    version.major() << 24 | version.minor()
-                );
-            }
-            BaseFunction::GetImplementationID => {
-                let id = sbi_rt::get_sbi_impl_id();
-                gprs.set_reg(GprIndex::A1, id);
-            }
-            BaseFunction::GetImplementationVersion => {
-                let impl_version = sbi_rt::get_sbi_impl_version();
-                gprs.set_reg(GprIndex::A1, impl_version);
-            }
-            BaseFunction::ProbeSbiExtension(extension) => {
-                let extension = sbi_rt::probe_extension(extension as usize).raw;
-                gprs.set_reg(GprIndex::A1, extension);
-            }
-            BaseFunction::GetMachineVendorID => {
-                let mvendorid = sbi_rt::get_mvendorid();
-                gprs.set_reg(GprIndex::A1, mvendorid);
-            }
-            BaseFunction::GetMachineArchitectureID => {
-                let marchid = sbi_rt::get_marchid();
-                gprs.set_reg(GprIndex::A1, marchid);
-            }
-            BaseFunction::GetMachineImplementationID => {
-                let mimpid = sbi_rt::get_mimpid();
-                gprs.set_reg(GprIndex::A1, mimpid);
-            }
-        }
-        gprs.set_reg(GprIndex::A0, 0);
-        Ok(())
-    }
-
-    fn handle_pmu_function(
-        &self,
-        pmu: PmuFunction,
-        gprs: &mut GeneralPurposeRegisters,
-    ) -> HyperResult<()> {
-        gprs.set_reg(GprIndex::A0, 0);
-        match pmu {
-            PmuFunction::GetNumCounters => gprs.set_reg(GprIndex::A1, sbi_rt::pmu_num_counters()),
-            PmuFunction::GetCounterInfo(counter_index) => {
-                let sbi_ret = pmu_counter_get_info(counter_index as usize);
-                gprs.set_reg(GprIndex::A0, sbi_ret.error);
-                gprs.set_reg(GprIndex::A1, sbi_ret.value);
-            }
-            PmuFunction::StopCounter {
-                counter_index,
-                counter_mask,
-                stop_flags,
-            } => {
-                let sbi_ret = pmu_counter_stop(
-                    counter_index as usize,
-                    counter_mask as usize,
-                    stop_flags as usize,
-                );
-                gprs.set_reg(GprIndex::A0, sbi_ret.error);
-                gprs.set_reg(GprIndex::A1, sbi_ret.value);
-            }
-        }
-        Ok(())
-    }
-
-    fn handle_rfnc_function(
-        &self,
-        rfnc: RemoteFenceFunction,
-        gprs: &mut GeneralPurposeRegisters,
-    ) -> HyperResult<()> {
-        gprs.set_reg(GprIndex::A0, 0);
-        match rfnc {
-            RemoteFenceFunction::FenceI {
-                hart_mask,
-                hart_mask_base,
-            } => {
-                let sbi_ret = sbi_rt::remote_fence_i(hart_mask as usize, hart_mask_base as usize);
-                gprs.set_reg(GprIndex::A0, sbi_ret.error);
-                gprs.set_reg(GprIndex::A1, sbi_ret.value);
-            }
-            RemoteFenceFunction::RemoteSFenceVMA {
-                hart_mask,
-                hart_mask_base,
-                start_addr,
-                size,
-            } => {
-                let sbi_ret = sbi_rt::remote_sfence_vma(
-                    hart_mask as usize,
-                    hart_mask_base as usize,
-                    start_addr as usize,
-                    size as usize,
-                );
-                gprs.set_reg(GprIndex::A0, sbi_ret.error);
-                gprs.set_reg(GprIndex::A1, sbi_ret.value);
-            }
-        }
-        Ok(())
-    }
-}
+use core::panic;
+
+use alloc::{boxed::Box, vec::Vec};
+use arrayvec::ArrayVec;
+
+use super::{
+    devices::{
+        aia::{num_guest_interrupt_files, AiaConfig, VirtAia},
+        plic::{PlicConfig, VirtPlic},
+        ConsoleSink, HostConsole, MmioAccess, MmioBus, MmioDevice, VirtualInterruptController,
+    },
+    ept::{GStageMode, GStagePageTable},
+    has_sstc,
+    regs::GeneralPurposeRegisters,
+    sbi::PmuFunction,
+    sbi::{
+        BaseFunction, DebugConsoleFunction, HartState, HsmFunction, IpiFunction,
+        RemoteFenceFunction, ResetFunction, ResetReason, ResetType, SbiImplementation,
+        SuspendFunction, HART_SUSPEND_TYPE_NON_RETENTIVE, HART_SUSPEND_TYPE_RETENTIVE,
+        SLEEP_TYPE_SUSPEND_TO_RAM,
+    },
+    traps,
+    vcpu::{self, VmCpuRegisters, VmCpuStatus},
+    virt_inst::{
+        CsrOp, CsrOperand, VirtualInstruction, CSR_CYCLE, CSR_HPMCOUNTER31, CSR_INSTRET, CSR_SATP,
+        CSR_SIREG, CSR_STOPEI, CSR_TIME,
+    },
+    virt_pmu::{self, FirmwareEvent, FIXED_COUNTERS, MAX_COUNTERS},
+    vm_pages::{GuestMemory, VmPages, VmRegion, VmRegionList, VmRegionType},
+    vmexit::{GuestAccessType, PrivilegeLevel, VmExitAction, VmExitHandler},
+    vmid::{Vmid, HGATP_VMID_SHIFT},
+    HyperCallMsg, RiscvCsrTrait, CSR,
+};
+use crate::{
+    arch::sbi::{
+        SBI_ERR_ALREADY_AVAILABLE, SBI_ERR_DENIED, SBI_ERR_INAVLID_PARAM, SBI_ERR_INVALID_ADDRESS,
+        SBI_ERR_NOT_SUPPORTED, SBI_ERR_NO_SHMEM,
+    },
+    devices::aia::IMSIC_PAGE_SIZE,
+    memory::PAGE_SIZE_4K,
+    vcpus::VM_CPUS_MAX,
+    GprIndex, GuestPageTableTrait, GuestPhysAddr, GuestVirtAddr, HostPhysAddr, HyperCraftHal,
+    HyperError, HyperResult, PerCpu, VCpu, VmCpus, VmExitInfo,
+};
+use page_table_entry::MappingFlags;
+
+/// Configuration of a VM.
+pub struct VmConfig {
+    /// The virtual interrupt controller delivering external interrupts to the guest.
+    pub irq_controller: Box<dyn VirtualInterruptController>,
+    /// The sink of the guest's console.
+    pub console: Box<dyn ConsoleSink>,
+    /// The embedder hooks called on the exits of the vCPUs, if any.
+    pub exit_handler: Option<Box<dyn VmExitHandler>>,
+    /// The SBI implementation presented to the guest.
+    pub sbi: SbiImplementation,
+}
+
+impl VmConfig {
+    /// Creates a configuration using a virtual PLIC configured by `plic`.
+    pub fn with_plic(plic: PlicConfig) -> Self {
+        Self {
+            irq_controller: Box::new(VirtPlic::new(plic)),
+            console: Box::new(HostConsole),
+            exit_handler: None,
+            sbi: SbiImplementation::from_host(),
+        }
+    }
+
+    /// Creates a configuration using a virtual AIA, an APLIC in MSI mode and an IMSIC interrupt
+    /// file per vCPU, configured by `aia`.
+    pub fn with_aia(aia: AiaConfig) -> Self {
+        Self {
+            irq_controller: Box::new(VirtAia::new(aia)),
+            console: Box::new(HostConsole),
+            exit_handler: None,
+            sbi: SbiImplementation::from_host(),
+        }
+    }
+
+    /// Sends the guest's console to `console` instead of the host's SBI console.
+    pub fn with_console(mut self, console: Box<dyn ConsoleSink>) -> Self {
+        self.console = console;
+        self
+    }
+
+    /// Lets `exit_handler` see the exits of the vCPUs before the built-in handling.
+    pub fn with_exit_handler(mut self, exit_handler: Box<dyn VmExitHandler>) -> Self {
+        self.exit_handler = Some(exit_handler);
+        self
+    }
+
+    /// Presents `sbi` to the guest instead of the host's firmware identity with the emulated
+    /// extensions.
+    pub fn with_sbi(mut self, sbi: SbiImplementation) -> Self {
+        self.sbi = sbi;
+        self
+    }
+}
+
+impl Default for VmConfig {
+    /// The configuration of a VM on the QEMU virt machine.
+    fn default() -> Self {
+        Self::with_plic(PlicConfig::default())
+    }
+}
+
+/// Why [`VM::run`] returned.
+#[derive(Debug, PartialEq)]
+pub enum VmRunOutcome {
+    /// The guest shut the VM down through SBI SRST. All its vCPUs are powered off.
+    Shutdown(ResetReason),
+    /// The guest requested a cold or warm reset through SBI SRST. The vCPUs are back in their
+    /// initial state, running the boot vCPU again reboots the guest.
+    Reset(ResetType, ResetReason),
+    /// The vCPU stopped itself through SBI HSM `hart_stop`, or was stopped by a system reset.
+    Halted,
+    /// Handling an exit of the vCPU failed, the VM can't go on.
+    Fatal(HyperError),
+    /// The vCPU executed `wfi` with no interrupt pending. The hart can run another guest, or
+    /// wait for an interrupt itself, before running the vCPU again.
+    Yield,
+    /// The vCPU suspended itself through SBI HSM `hart_suspend`, or suspended the VM through
+    /// SBI SUSP, and is parked off the hart. Running it again waits for an interrupt on the hart
+    /// with `wfi`, and resumes the vCPU if that left it with a virtual interrupt to take;
+    /// otherwise `run` returns `Suspended` again.
+    Suspended,
+    /// The host's own timer deadline, armed with `PerCpu::set_host_timer`, passed. Its interrupt
+    /// is left pending for the host to take; the host arms its next deadline, or disarms its
+    /// timer, with `set_host_timer`.
+    HostTimer,
+}
+
+/// A VM that is being run. It runs one vCPU at a time: `run` borrows the VM mutably, so while a
+/// vCPU runs the others are put, and what is queued for them, e.g. virtual interrupts, is picked
+/// up the next time they run.
+pub struct VM<H: HyperCraftHal, G: GuestPageTableTrait> {
+    vcpus: VmCpus<H>,
+    gpt: G,
+    vm_pages: VmPages,
+    regions: VmRegionList,
+    irq_controller: Box<dyn VirtualInterruptController>,
+    mmio_bus: MmioBus,
+    console: Box<dyn ConsoleSink>,
+    exit_handler: Option<Box<dyn VmExitHandler>>,
+    gstage_mode: GStageMode,
+    sbi: SbiImplementation,
+    vmid: Vmid,
+    last_reset: Option<(ResetType, ResetReason)>,
+    // The vCPU of the VM that last ran on each hart, indexed by CPU id.
+    last_vcpus: Vec<Option<usize>>,
+}
+
+impl<H: HyperCraftHal, G: GuestPageTableTrait> VM<H, G> {
+    /// Create a new VM with `vcpus` vCPUs and `gpt` as the guest page table.
+    pub fn new(vcpus: VmCpus<H>, gpt: G) -> HyperResult<Self>
+    where
+        G: GStagePageTable,
+    {
+        Self::new_with_config(vcpus, gpt, VmConfig::default())
+    }
+
+    /// Create a new VM with `vcpus` vCPUs and `gpt` as the guest page table, configured by
+    /// `config`. The guest memory must then be mapped with `map` and `map_region`, within the
+    /// regions added with `add_region`. Guest physical addresses are translated in the G-stage
+    /// mode `gpt` is built for.
+    pub fn new_with_config(mut vcpus: VmCpus<H>, mut gpt: G, config: VmConfig) -> HyperResult<Self>
+    where
+        G: GStagePageTable,
+    {
+        if !G::GSTAGE_MODE.is_supported() {
+            return Err(HyperError::NotSupported);
+        }
+        // The root page table spans 16 KiB, the low bits of its PPN are ignored.
+        if gpt.token() & 0b11 != 0 {
+            return Err(HyperError::InvalidParam);
+        }
+        // The guest's time starts at zero, and is shared by all its vCPUs wherever they run.
+        let time_delta = 0usize.wrapping_sub(riscv::register::time::read());
+        for vcpu_id in 0..VM_CPUS_MAX {
+            if let Ok(vcpu) = vcpus.get_vcpu(vcpu_id) {
+                vcpu.set_time_delta(time_delta);
+            }
+        }
+        let mut regions = VmRegionList::new();
+        regions.add(
+            config.irq_controller.base(),
+            config.irq_controller.size(),
+            VmRegionType::Mmio,
+        )?;
+        // Guest interrupt files are mapped for the guest to access directly, emulated interrupt
+        // files trap like any other MMIO.
+        for vcpu_id in 0..VM_CPUS_MAX {
+            let (Ok(vcpu), Some(msi_page)) = (
+                vcpus.get_vcpu(vcpu_id),
+                config.irq_controller.msi_page(vcpu_id),
+            ) else {
+                continue;
+            };
+            match config.irq_controller.guest_interrupt_file(vcpu_id) {
+                Some(file) => {
+                    if file.guest_index == 0 || file.guest_index > num_guest_interrupt_files() {
+                        return Err(HyperError::InvalidParam);
+                    }
+                    regions.add(msi_page, IMSIC_PAGE_SIZE, VmRegionType::Imsic)?;
+                    gpt.map(
+                        msi_page,
+                        file.host_paddr,
+                        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+                    )?;
+                    vcpu.set_guest_interrupt_file(file);
+                }
+                None => regions.add(msi_page, IMSIC_PAGE_SIZE, VmRegionType::Mmio)?,
+            }
+        }
+        Ok(Self {
+            vcpus,
+            gpt,
+            vm_pages: VmPages::default(),
+            regions,
+            irq_controller: config.irq_controller,
+            mmio_bus: MmioBus::new(),
+            console: config.console,
+            exit_handler: config.exit_handler,
+            gstage_mode: G::GSTAGE_MODE,
+            sbi: config.sbi,
+            vmid: Vmid::new(),
+            last_reset: None,
+            last_vcpus: Vec::new(),
+        })
+    }
+
+    /// Attaches the emulated `device` to the guest physical range `[base, base + size)`, which
+    /// becomes an MMIO region. Fails if the range overlaps another region.
+    pub fn register_mmio_device(
+        &mut self,
+        base: GuestPhysAddr,
+        size: usize,
+        device: Box<dyn MmioDevice>,
+    ) -> HyperResult<()> {
+        self.regions.add(base, size, VmRegionType::Mmio)?;
+        self.mmio_bus.register(base, size, device).map_err(|err| {
+            self.regions.remove(base).unwrap();
+            err
+        })
+    }
+
+    /// Designates the guest physical range `[start, start + size)` for `region_type`. Pages can
+    /// only be mapped within regions of a mappable type. Fails if the range overlaps another
+    /// region.
+    pub fn add_region(
+        &mut self,
+        start: GuestPhysAddr,
+        size: usize,
+        region_type: VmRegionType,
+    ) -> HyperResult<()> {
+        self.regions.add(start, size, region_type)
+    }
+
+    /// Removes the removable region starting at `start` and unmaps its pages.
+    pub fn remove_region(&mut self, start: GuestPhysAddr) -> HyperResult<()> {
+        let region = self.regions.find(start).ok_or(HyperError::NotFound)?;
+        if region.start() != start {
+            return Err(HyperError::NotFound);
+        }
+        if !region.region_type().is_removable() {
+            return Err(HyperError::InvalidParam);
+        }
+        let region = self.regions.remove(start)?;
+        for gpa in (region.start()..region.end()).step_by(PAGE_SIZE_4K) {
+            // Pages of the region that were never mapped are fine.
+            let _ = self.gpt.unmap(gpa);
+        }
+        self.flush_gstage_tlb();
+        Ok(())
+    }
+
+    /// Returns the region containing the guest physical address `gpa`, if any.
+    pub fn region(&self, gpa: GuestPhysAddr) -> Option<&VmRegion> {
+        self.regions.find(gpa)
+    }
+
+    /// Maps the guest physical page at `gpa` to the host physical page at `hpa` with `flags`. The
+    /// page must lie in a mappable region.
+    pub fn map(
+        &mut self,
+        gpa: GuestPhysAddr,
+        hpa: HostPhysAddr,
+        flags: MappingFlags,
+    ) -> HyperResult<()> {
+        self.regions.check_mapping(gpa, PAGE_SIZE_4K)?;
+        self.gpt.map(gpa, hpa, flags)
+    }
+
+    /// Maps the guest physical range `[gpa, gpa + size)` to the host physical range starting at
+    /// `hpa` with `flags`. The range must lie in a single mappable region.
+    pub fn map_region(
+        &mut self,
+        gpa: GuestPhysAddr,
+        hpa: HostPhysAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> HyperResult<()> {
+        self.regions.check_mapping(gpa, size)?;
+        self.gpt.map_region(gpa, hpa, size, flags)
+    }
+
+    /// Unmaps the guest physical page at `gpa` and flushes it from the G-stage TLBs.
+    pub fn unmap(&mut self, gpa: GuestPhysAddr) -> HyperResult<()> {
+        self.gpt.unmap(gpa)?;
+        self.flush_gstage_tlb();
+        Ok(())
+    }
+
+    /// Returns an accessor for the guest physical memory of the VM, on this CPU.
+    pub fn guest_phys_memory(&mut self) -> GuestMemory<'_> {
+        GuestMemory::new(self.active_hgatp(), 0)
+    }
+
+    /// Returns an accessor for the guest virtual memory of the vCPU `vcpu_id`, translated by the
+    /// VS-stage page table it last ran with, on this CPU.
+    pub fn guest_virt_memory(&mut self, vcpu_id: usize) -> HyperResult<GuestMemory<'_>> {
+        let vsatp = self.vcpus.get_vcpu(vcpu_id)?.vsatp();
+        Ok(GuestMemory::new(self.active_hgatp(), vsatp))
+    }
+
+    /// Initialize `VCpu` by `vcpu_id`.
+    pub fn init_vcpu(&mut self, vcpu_id: usize) {
+        let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
+        vcpu.init_page_map(self.gstage_mode.hgatp(self.gpt.token()));
+    }
+
+    /// Flushes the G-stage TLB entries of the VM on the harts its vCPUs ran on, to be called after
+    /// changing mappings of `gpt`.
+    pub fn flush_gstage_tlb(&mut self) {
+        let vmid = self.vmid.vmid();
+        let this_pcpu = PerCpu::<H>::this_cpu().hart_id();
+        // The other harts, as (`hart_mask_base`, `hart_mask`) pairs for SBI.
+        let mut remote_masks = ArrayVec::<(usize, usize), VM_CPUS_MAX>::new();
+        for vcpu_id in 0..VM_CPUS_MAX {
+            // A vCPU that never ran left nothing in any TLB.
+            let Some(pcpu_id) = self.vcpus.get_vcpu(vcpu_id).ok().and_then(|v| v.pcpu_id()) else {
+                continue;
+            };
+            if pcpu_id == this_pcpu {
+                continue;
+            }
+            let base = pcpu_id - pcpu_id % usize::BITS as usize;
+            let bit = 1 << (pcpu_id - base);
+            match remote_masks
+                .iter_mut()
+                .find(|(mask_base, _)| *mask_base == base)
+            {
+                Some((_, mask)) => *mask |= bit,
+                None => remote_masks.push((base, bit)),
+            }
+        }
+        unsafe { core::arch::riscv64::hfence_gvma_vmid(vmid) };
+        for (base, mask) in remote_masks {
+            sbi_rt::remote_hfence_gvma_vmid(mask, base, 0, 0, vmid);
+        }
+    }
+
+    /// Returns the type and reason of the last system reset or shutdown the guest requested
+    /// through SBI SRST, if any.
+    pub fn last_reset(&self) -> Option<(ResetType, ResetReason)> {
+        self.last_reset
+    }
+
+    #[allow(unused_variables, deprecated)]
+    /// Run the host VM's vCPU with ID `vcpu_id` until it stops, the guest shuts down or resets
+    /// the VM, the vCPU becomes idle or an unrecoverable error occurs.
+    pub fn run(&mut self, vcpu_id: usize) -> VmRunOutcome {
+        let mut vm_exit_info: VmExitInfo;
+        let mut gprs = GeneralPurposeRegisters::default();
+        match self.vcpus.get_vcpu(vcpu_id) {
+            // A guest interrupt file is part of one hart's IMSIC, its vCPU can't run elsewhere.
+            Ok(vcpu)
+                if vcpu.guest_interrupt_file().map_or(false, |file| {
+                    file.hart_id != PerCpu::<H>::this_cpu().hart_id()
+                }) =>
+            {
+                return VmRunOutcome::Fatal(HyperError::BadState)
+            }
+            Ok(vcpu) => {
+                // VS-stage TLB entries are only tagged with the VMID and the guest's ASID, so the
+                // translations another vCPU of the VM left on this hart would be hit as well.
+                let cpu_id = PerCpu::<H>::this_cpu().cpu_id();
+                if self.last_vcpus.len() <= cpu_id {
+                    self.last_vcpus.resize(cpu_id + 1, None);
+                }
+                if self.last_vcpus[cpu_id]
+                    .replace(vcpu_id)
+                    .map_or(false, |last| last != vcpu_id)
+                {
+                    vcpu.queue_fences(vcpu::PENDING_HFENCE_VVMA);
+                }
+                vcpu.load();
+                // Without Sstc, the hart's timer serves the emulated timer of the vCPU running on
+                // it along with the host's own deadline, and only the host's once it's put.
+                if !has_sstc() {
+                    sync_vcpu_timer(vcpu);
+                }
+            }
+            Err(err) => return VmRunOutcome::Fatal(err),
+        }
+        // A suspended vCPU only resumes once it has an interrupt to take.
+        if self.vcpus.get_vcpu(vcpu_id).unwrap().status() == VmCpuStatus::Suspended {
+            let outcome = match self.wait_for_wakeup(vcpu_id) {
+                Ok(true) => None,
+                Ok(false) => Some(VmRunOutcome::Suspended),
+                Err(err) => Some(VmRunOutcome::Fatal(err)),
+            };
+            if let Some(outcome) = outcome {
+                self.put_vcpu(vcpu_id);
+                return outcome;
+            }
+        }
+        let outcome = loop {
+            let mut len = 4;
+            let mut advance_pc = false;
+            // An exception to reflect to the guest, and its trap value.
+            let mut exception = None;
+            // Set to return to the embedder once the exit is handled.
+            let mut outcome = None;
+            {
+                let pcpu = PerCpu::<H>::this_cpu();
+                let vmid = self.vmid.activate(pcpu.vmid_generation());
+                let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
+                vcpu.load_hgatp(vmid);
+                vcpu.set_pcpu_id(pcpu.hart_id());
+                vcpu.set_status(VmCpuStatus::Running);
+                vm_exit_info = vcpu.run();
+                vcpu.save_gprs(&mut gprs);
+            }
+
+            let action = match self.exit_handler.as_mut() {
+                Some(handler) => handler.handle_exit(vcpu_id, &vm_exit_info, &mut gprs),
+                None => Ok(VmExitAction::Default),
+            };
+            let result = match action {
+                Err(err) => Err(err),
+                Ok(VmExitAction::Resume) => Ok(()),
+                Ok(VmExitAction::Advance(inst_len)) => {
+                    len = inst_len;
+                    advance_pc = true;
+                    Ok(())
+                }
+                Ok(VmExitAction::Default) => match vm_exit_info {
+                    VmExitInfo::Ecall(sbi_msg) => {
+                        let eid = gprs.reg(GprIndex::A7);
+                        if let Some(sbi_msg) = sbi_msg.filter(|_| self.sbi.has_extension(eid)) {
+                            advance_pc = true;
+                            match sbi_msg {
+                                HyperCallMsg::Base(base) => {
+                                    self.handle_base_function(base, &mut gprs)
+                                }
+                                HyperCallMsg::GetChar => {
+                                    let mut c = [0u8];
+                                    let ret = match self.console.read(&mut c) {
+                                        0 => usize::MAX,
+                                        _ => c[0] as usize,
+                                    };
+                                    gprs.set_reg(GprIndex::A0, ret);
+                                    Ok(())
+                                }
+                                HyperCallMsg::PutChar(c) => {
+                                    self.console.write(&[c as u8]);
+                                    gprs.set_reg(GprIndex::A0, 0);
+                                    Ok(())
+                                }
+                                HyperCallMsg::DebugConsole(dbcn) => {
+                                    self.handle_dbcn_function(dbcn, &mut gprs);
+                                    Ok(())
+                                }
+                                HyperCallMsg::SetTimer(timer) => {
+                                    self.record_fw_event(vcpu_id, FirmwareEvent::SetTimer);
+                                    self.set_vcpu_timer(vcpu_id, timer as u64)
+                                }
+                                HyperCallMsg::Reset(ResetFunction::Reset {
+                                    reset_type,
+                                    reason,
+                                }) => {
+                                    self.handle_system_reset(vcpu_id, reset_type, reason);
+                                    // The vCPU state was reset, don't write back the registers of
+                                    // the call.
+                                    self.vcpus.get_vcpu(vcpu_id).unwrap().save_gprs(&mut gprs);
+                                    advance_pc = false;
+                                    outcome = Some(match reset_type {
+                                        ResetType::Shutdown => VmRunOutcome::Shutdown(reason),
+                                        _ => VmRunOutcome::Reset(reset_type, reason),
+                                    });
+                                    Ok(())
+                                }
+                                HyperCallMsg::RemoteFence(rfnc) => {
+                                    let event = match rfnc {
+                                        RemoteFenceFunction::FenceI { .. } => {
+                                            FirmwareEvent::FenceISent
+                                        }
+                                        RemoteFenceFunction::RemoteSFenceVMA { .. } => {
+                                            FirmwareEvent::SFenceVmaSent
+                                        }
+                                        _ => FirmwareEvent::SFenceVmaAsidSent,
+                                    };
+                                    self.record_fw_event(vcpu_id, event);
+                                    self.handle_rfnc_function(vcpu_id, rfnc, &mut gprs)
+                                }
+                                HyperCallMsg::PMU(pmu) => {
+                                    self.handle_pmu_function(vcpu_id, pmu, &mut gprs)
+                                }
+                                HyperCallMsg::SendIpi(ipi) => {
+                                    if !matches!(ipi, IpiFunction::LegacyClearIpi) {
+                                        self.record_fw_event(vcpu_id, FirmwareEvent::IpiSent);
+                                    }
+                                    self.handle_ipi_function(vcpu_id, ipi, &mut gprs)
+                                }
+                                HyperCallMsg::Hsm(hsm) => self
+                                    .handle_hsm_function(vcpu_id, hsm, &mut gprs)
+                                    .map(|advance| advance_pc = advance),
+                                HyperCallMsg::Suspend(susp) => self
+                                    .handle_suspend_function(vcpu_id, susp, &mut gprs)
+                                    .map(|advance| advance_pc = advance),
+                                _ => {
+                                    gprs.set_reg(GprIndex::A0, SBI_ERR_NOT_SUPPORTED as usize);
+                                    Ok(())
+                                }
+                            }
+                        } else {
+                            // Unknown or hidden extension, or unknown function.
+                            advance_pc = true;
+                            gprs.set_reg(GprIndex::A0, SBI_ERR_NOT_SUPPORTED as usize);
+                            Ok(())
+                        }
+                    }
+                    VmExitInfo::PageFault {
+                        fault_addr,
+                        falut_pc,
+                        fault_vaddr,
+                        inst,
+                        access,
+                        priv_level,
+                    } => {
+                        match access {
+                            GuestAccessType::Fetch => {
+                                warn!(
+                                    "vCPU {} fetched from unmapped address {:#x}",
+                                    vcpu_id, fault_addr
+                                );
+                                exception =
+                                    Some((traps::exception::INST_ACCESSS_FAULT, fault_vaddr));
+                            }
+                            _ => match self
+                                .handle_page_fault(vcpu_id, falut_pc, inst, fault_addr, &mut gprs)
+                            {
+                                Ok(inst_len) => {
+                                    len = inst_len;
+                                    advance_pc = true;
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "vCPU {} {:?} page fault at {:#x} addr@{:#x}: {:?}",
+                                        vcpu_id, priv_level, falut_pc, fault_addr, err
+                                    );
+                                    let cause = if access == GuestAccessType::Load {
+                                        traps::exception::LOAD_ACCESS_FAULT
+                                    } else {
+                                        traps::exception::STORE_ACCESS_FAULT
+                                    };
+                                    exception = Some((cause, fault_vaddr));
+                                }
+                            },
+                        }
+                        Ok(())
+                    }
+                    VmExitInfo::VirtualInstruction {
+                        fault_pc,
+                        inst,
+                        priv_level,
+                    } => {
+                        match self.handle_virtual_instruction(
+                            vcpu_id, fault_pc, inst, priv_level, &mut gprs,
+                        ) {
+                            Ok((inst_len, idle)) => {
+                                len = inst_len;
+                                advance_pc = true;
+                                if idle {
+                                    outcome = Some(VmRunOutcome::Yield);
+                                }
+                            }
+                            Err(_) => {
+                                // The guest has no H extension, so anything we don't emulate is an
+                                // illegal instruction to it.
+                                exception = Some((traps::exception::ILLEGAL_INST, inst as usize));
+                            }
+                        }
+                        Ok(())
+                    }
+                    VmExitInfo::GuestException { cause, tval } => {
+                        exception = Some((cause, tval));
+                        Ok(())
+                    }
+                    VmExitInfo::TimerInterruptEmulation => {
+                        // With Sstc the guest's timer is `vstimecmp`, the host timer only fires
+                        // for the host.
+                        let host_timer = if has_sstc() {
+                            Ok(true)
+                        } else {
+                            self.handle_timer_irq(vcpu_id)
+                        };
+                        host_timer.map(|host_timer| {
+                            if host_timer {
+                                outcome = Some(VmRunOutcome::HostTimer);
+                            }
+                        })
+                    }
+                    VmExitInfo::ExternalInterruptEmulation => {
+                        self.handle_irq(vcpu_id);
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                },
+            };
+            if let Err(err) = result {
+                error!(
+                    "vCPU {} failed to handle {:?}: {:?}",
+                    vcpu_id, vm_exit_info, err
+                );
+                break VmRunOutcome::Fatal(err);
+            }
+
+            let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
+            vcpu.restore_gprs(&gprs);
+            if advance_pc {
+                vcpu.advance_pc(len);
+            }
+            if let Some((cause, tval)) = exception {
+                vcpu.inject_exception(cause, tval);
+                let event = match cause {
+                    traps::exception::LOAD_ACCESS_FAULT => Some(FirmwareEvent::AccessLoad),
+                    traps::exception::STORE_ACCESS_FAULT => Some(FirmwareEvent::AccessStore),
+                    traps::exception::ILLEGAL_INST => Some(FirmwareEvent::IllegalInsn),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    vcpu.pmu().record_fw_event(event);
+                }
+            }
+            if let Some(outcome) = outcome {
+                if vcpu.status() == VmCpuStatus::Running {
+                    vcpu.set_status(VmCpuStatus::Runnable);
+                }
+                break outcome;
+            }
+            match vcpu.status() {
+                VmCpuStatus::PoweredOff => break VmRunOutcome::Halted,
+                VmCpuStatus::Suspended => break VmRunOutcome::Suspended,
+                _ => vcpu.set_status(VmCpuStatus::Runnable),
+            }
+        };
+        self.put_vcpu(vcpu_id);
+        outcome
+    }
+}
+
+// Privaie methods implementation
+impl<H: HyperCraftHal, G: GuestPageTableTrait> VM<H, G> {
+    /// Saves the state of the vCPU `vcpu_id` as it leaves this hart at the end of `run`.
+    fn put_vcpu(&mut self, vcpu_id: usize) {
+        self.vcpus.get_vcpu(vcpu_id).unwrap().put();
+        if !has_sstc() {
+            let pcpu = PerCpu::<H>::this_cpu();
+            pcpu.timer().put();
+            pcpu.program_timer();
+        }
+    }
+
+    /// Returns the `hgatp` of the VM, with a VMID valid on this CPU.
+    fn active_hgatp(&mut self) -> usize {
+        let vmid = self
+            .vmid
+            .activate(PerCpu::<H>::this_cpu().vmid_generation());
+        self.gstage_mode.hgatp(self.gpt.token()) | (vmid << HGATP_VMID_SHIFT)
+    }
+
+    fn handle_page_fault(
+        &mut self,
+        vcpu_id: usize,
+        inst_addr: GuestVirtAddr,
+        inst: u32,
+        fault_addr: GuestPhysAddr,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<usize> {
+        // Only MMIO regions are meant to fault, anything else is an access to memory the VM
+        // doesn't have.
+        match self.regions.find(fault_addr).map(|r| r.region_type()) {
+            Some(VmRegionType::Mmio) => {}
+            Some(region_type) => {
+                error!(
+                    "inst_addr: {:#x}, fault_addr: {:#x} unmapped in {:?} region",
+                    inst_addr, fault_addr, region_type
+                );
+                return Err(HyperError::PageFault);
+            }
+            None => {
+                error!("inst_addr: {:#x}, fault_addr: {:#x}", inst_addr, fault_addr);
+                return Err(HyperError::PageFault);
+            }
+        }
+        let irq_controller_base = self.irq_controller.base();
+        let is_irq_controller = fault_addr >= irq_controller_base
+            && fault_addr < irq_controller_base + self.irq_controller.size();
+        // The MSI page of a vCPU's emulated interrupt file, and that vCPU.
+        let msi_target = (0..VM_CPUS_MAX).find_map(|id| {
+            self.irq_controller
+                .msi_page(id)
+                .filter(|&page| fault_addr >= page && fault_addr < page + IMSIC_PAGE_SIZE)
+                .map(|page| (id, page))
+        });
+        if !is_irq_controller && msi_target.is_none() && !self.mmio_bus.contains(fault_addr) {
+            // An MMIO region added by the embedder, which should have handled the exit.
+            error!(
+                "inst_addr: {:#x}, fault_addr: {:#x} has no device",
+                inst_addr, fault_addr
+            );
+            return Err(HyperError::PageFault);
+        }
+
+        let access = self.decode_mmio_access(inst_addr, inst)?;
+        if is_irq_controller {
+            let offset = fault_addr - irq_controller_base;
+            emulate_mmio_access(&access, self.irq_controller.as_mut(), offset, gprs)?;
+            self.update_external_irqs(vcpu_id);
+        } else if let Some((target, page)) = msi_target {
+            let file = self
+                .irq_controller
+                .interrupt_file(target)
+                .ok_or(HyperError::PageFault)?;
+            emulate_mmio_access(&access, file, fault_addr - page, gprs)?;
+            self.update_external_irqs(vcpu_id);
+        } else {
+            let (offset, device) = self.mmio_bus.find_mut(fault_addr).unwrap();
+            emulate_mmio_access(&access, device, offset, gprs)?;
+        }
+        Ok(access.len)
+    }
+
+    /// Decodes the access that caused an MMIO fault from `htinst`, or from the instruction at
+    /// `inst_addr` if the hardware did not report a transformed instruction.
+    fn decode_mmio_access(&self, inst_addr: GuestVirtAddr, htinst: u32) -> HyperResult<MmioAccess> {
+        if htinst == 0 {
+            let inst = self.vm_pages.fetch_guest_instruction(inst_addr)?;
+            MmioAccess::decode(inst)
+        } else {
+            MmioAccess::decode_transformed(htinst)
+        }
+    }
+
+    /// Arms the timer of the vCPU to fire at `deadline` in the guest's time base, for SBI
+    /// `set_timer`. Uses `vstimecmp` with Sstc, else the vCPU's timer emulated over the host
+    /// timer.
+    fn set_vcpu_timer(&mut self, vcpu_id: usize, deadline: u64) -> HyperResult<()> {
+        CSR.hvip
+            .read_and_clear_bits(traps::interrupt::VIRTUAL_SUPERVISOR_TIMER);
+        let vcpu = self.vcpus.get_vcpu(vcpu_id)?;
+        if has_sstc() {
+            vcpu.set_timer(deadline);
+            return Ok(());
+        }
+        let host_deadline = deadline.wrapping_sub(vcpu.time_delta() as u64);
+        vcpu.timer().set(host_deadline);
+        sync_vcpu_timer(vcpu);
+        Ok(())
+    }
+
+    /// Handles the host timer interrupt without Sstc, which fires for the emulated timer of the
+    /// vCPU `vcpu_id` running on this hart, or for the host's own deadline. Returns whether the
+    /// host's deadline passed, in which case the interrupt stays pending for the host.
+    fn handle_timer_irq(&mut self, vcpu_id: usize) -> HyperResult<bool> {
+        sync_vcpu_timer(self.vcpus.get_vcpu(vcpu_id)?);
+        Ok(PerCpu::<H>::this_cpu()
+            .timer()
+            .host_expired(riscv::register::time::read() as u64))
+    }
+
+    /// Performs a system reset of the VM for SBI SRST. Shutdown powers off all vCPUs, so that
+    /// `run` halts them. Cold and warm resets put the vCPUs and the interrupt controller back in
+    /// their initial state, with the boot vCPU restarting at the entry point. `vcpu_id` is the
+    /// vCPU running on this hart.
+    ///
+    /// The vCPUs are reset where they are: the VM runs one vCPU at a time, so all the others are
+    /// put and hold nothing on any hart. The calling vCPU is put for the reset and loaded again.
+    fn handle_system_reset(&mut self, vcpu_id: usize, reset_type: ResetType, reason: ResetReason) {
+        info!("VM system reset: {:?}, reason: {:?}", reset_type, reason);
+        self.vcpus.get_vcpu(vcpu_id).unwrap().put();
+        for id in 0..VM_CPUS_MAX {
+            let Ok(vcpu) = self.vcpus.get_vcpu(id) else {
+                continue;
+            };
+            debug_assert!(id == vcpu_id || vcpu.status() != VmCpuStatus::Running);
+            vcpu.timer().clear();
+            match reset_type {
+                ResetType::Shutdown => vcpu.set_status(VmCpuStatus::PoweredOff),
+                ResetType::ColdReset | ResetType::WarmReset => vcpu.reset(),
+            }
+        }
+        let vcpu = self.vcpus.get_vcpu(vcpu_id).unwrap();
+        vcpu.load();
+        if !has_sstc() {
+            sync_vcpu_timer(vcpu);
+        }
+        if reset_type != ResetType::Shutdown {
+            self.irq_controller.reset();
+        }
+        self.last_reset = Some((reset_type, reason));
+    }
+
+    /// Emulates the instruction at `inst_addr` that raised a virtual-instruction exception,
+    /// returning its length and whether the vCPU is now idle in `wfi`.
+    fn handle_virtual_instruction(
+        &mut self,
+        vcpu_id: usize,
+        inst_addr: GuestVirtAddr,
+        mut inst: u32,
+        priv_level: PrivilegeLevel,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<(usize, bool)> {
+        if inst == 0 {
+            inst = self.vm_pages.fetch_guest_instruction(inst_addr)?;
+        }
+        if inst & 0b11 != 0b11 {
+            return Err(HyperError::InvalidInstruction);
+        }
+        let is_user = priv_level == PrivilegeLevel::User;
+        match VirtualInstruction::decode(inst)? {
+            VirtualInstruction::Wfi
+            | VirtualInstruction::SfenceVma { .. }
+            | VirtualInstruction::SfenceInval
+                if is_user =>
+            {
+                return Err(HyperError::InvalidInstruction);
+            }
+            VirtualInstruction::Wfi => return Ok((4, self.is_idle(vcpu_id))),
+            VirtualInstruction::SfenceVma { vaddr, asid } => {
+                let asid = (asid != GprIndex::Zero).then(|| gprs.reg(asid));
+                if vaddr == GprIndex::Zero {
+                    local_hfence_vvma(0, 0, asid);
+                } else {
+                    local_hfence_vvma(gprs.reg(vaddr), PAGE_SIZE_4K, asid);
+                }
+            }
+            VirtualInstruction::SfenceInval => {}
+            VirtualInstruction::Csr { csr, op, rd, src } => {
+                let (operand, writes) = match src {
+                    CsrOperand::Reg(reg) => {
+                        (gprs.reg(reg), op == CsrOp::Write || reg != GprIndex::Zero)
+                    }
+                    CsrOperand::Imm(imm) => (imm, op == CsrOp::Write || imm != 0),
+                };
+                let user_counters = if is_user {
+                    Some(self.vcpus.get_vcpu(vcpu_id)?.guest_scounteren())
+                } else {
+                    None
+                };
+                let update = |old: usize| match op {
+                    CsrOp::Write => operand,
+                    CsrOp::Set => old | operand,
+                    CsrOp::Clear => old & !operand,
+                };
+                let old = if matches!(csr, CSR_SIREG | CSR_STOPEI) && !is_user {
+                    self.access_interrupt_file(vcpu_id, csr, writes.then_some(update))?
+                } else {
+                    let old = read_virtual_csr(csr, user_counters)?;
+                    if writes {
+                        write_virtual_csr(csr, is_user, update(old))?;
+                    }
+                    old
+                };
+                gprs.set_reg(rd, old);
+            }
+        }
+        Ok((4, false))
+    }
+
+    /// Emulates an access to `sireg` or `stopei` of the vCPU's emulated interrupt file, which
+    /// traps as the vCPU has no guest interrupt file. `update` computes the value written from
+    /// the old value, if the access writes. Returns the old value.
+    fn access_interrupt_file(
+        &mut self,
+        vcpu_id: usize,
+        csr: u16,
+        update: Option<impl FnOnce(usize) -> usize>,
+    ) -> HyperResult<usize> {
+        let file = self
+            .irq_controller
+            .interrupt_file(vcpu_id)
+            .ok_or(HyperError::InvalidInstruction)?;
+        let old = if csr == CSR_STOPEI {
+            // Any write claims the interrupt read.
+            let old = file.topei();
+            if update.is_some() {
+                file.claim();
+            }
+            old
+        } else {
+            // The guest's `siselect` is `vsiselect`, which doesn't trap.
+            let select: usize;
+            unsafe { core::arch::asm!("csrr {}, 0x250", out(reg) select) }; // vsiselect
+            let old = file.read_reg(select)?;
+            if let Some(update) = update {
+                file.write_reg(select, update(old))?;
+            }
+            old
+        };
+        self.update_external_irqs(vcpu_id);
+        Ok(old)
+    }
+
+    /// Returns whether the vCPU has no virtual interrupt pending that it enabled, i.e. its `wfi`
+    /// would stall. Checks the same interrupts as a suspended vCPU's wakeup does, including the
+    /// Sstc timer and the guest interrupt file's interrupt.
+    fn is_idle(&mut self, vcpu_id: usize) -> bool {
+        !self.vcpus.get_vcpu(vcpu_id).unwrap().has_wakeup_irq()
+    }
+
+    /// Asserts or deasserts the virtual external interrupt of the vCPUs according to the virtual
+    /// interrupt controller. `vcpu_id` is the vCPU running on this hart.
+    fn update_external_irqs(&mut self, vcpu_id: usize) {
+        for id in 0..VM_CPUS_MAX {
+            let has_interrupt = self.irq_controller.has_interrupt(id);
+            if id == vcpu_id {
+                if has_interrupt {
+                    CSR.hvip
+                        .read_and_set_bits(traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL);
+                } else {
+                    CSR.hvip
+                        .read_and_clear_bits(traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL);
+                }
+            } else if has_interrupt {
+                if let Ok(vcpu) = self.vcpus.get_vcpu(id) {
+                    vcpu.inject_interrupt(traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL);
+                }
+            }
+        }
+    }
+
+    fn handle_irq(&mut self, vcpu_id: usize) {
+        self.irq_controller.handle_host_irq();
+        self.update_external_irqs(vcpu_id);
+    }
+
+    fn handle_base_function(
+        &self,
+        base: BaseFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<()> {
+        let sbi = &self.sbi;
+        let value = match base {
+            BaseFunction::GetSepcificationVersion => sbi.spec_version,
+            BaseFunction::GetImplementationID => sbi.impl_id,
+            BaseFunction::GetImplementationVersion => sbi.impl_version,
+            BaseFunction::ProbeSbiExtension(extension) => {
+                sbi.has_extension(extension as usize) as usize
+            }
+            BaseFunction::GetMachineVendorID => sbi.mvendorid,
+            BaseFunction::GetMachineArchitectureID => sbi.marchid,
+            BaseFunction::GetMachineImplementationID => sbi.mimpid,
+        };
+        gprs.set_reg(GprIndex::A1, value);
+        gprs.set_reg(GprIndex::A0, 0);
+        Ok(())
+    }
+
+    fn handle_dbcn_function(
+        &mut self,
+        dbcn: DebugConsoleFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) {
+        // Large buffers are moved in chunks of this size.
+        const CHUNK_SIZE: usize = 256;
+
+        let (num_bytes, base_addr) = match dbcn {
+            DebugConsoleFunction::WriteByte { byte } => {
+                self.console.write(&[byte]);
+                gprs.set_reg(GprIndex::A0, 0);
+                gprs.set_reg(GprIndex::A1, 0);
+                return;
+            }
+            DebugConsoleFunction::Write {
+                num_bytes,
+                base_addr_lo,
+                base_addr_hi,
+            }
+            | DebugConsoleFunction::Read {
+                num_bytes,
+                base_addr_lo,
+                base_addr_hi,
+            } => {
+                // Guest physical addresses are at most XLEN bits wide.
+                if base_addr_hi != 0 {
+                    gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize);
+                    return;
+                }
+                (num_bytes as usize, base_addr_lo as usize)
+            }
+        };
+
+        let mut buf = [0u8; CHUNK_SIZE];
+        let mut done = 0;
+        let mut result = Ok(());
+        if let DebugConsoleFunction::Write { .. } = dbcn {
+            while done < num_bytes {
+                let chunk = &mut buf[..CHUNK_SIZE.min(num_bytes - done)];
+                result = self.vm_pages.copy_from_guest_phys(chunk, base_addr + done);
+                if result.is_err() {
+                    break;
+                }
+                self.console.write(chunk);
+                done += chunk.len();
+            }
+        } else {
+            let chunk = &mut buf[..CHUNK_SIZE.min(num_bytes)];
+            let count = self.console.read(chunk);
+            result = self.vm_pages.copy_to_guest_phys(base_addr, &chunk[..count]);
+            if result.is_ok() {
+                done = count;
+            }
+        }
+
+        if result.is_err() && done == 0 {
+            gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize);
+        } else {
+            gprs.set_reg(GprIndex::A0, 0);
+            gprs.set_reg(GprIndex::A1, done);
+        }
+    }
+
+    fn handle_pmu_function(
+        &mut self,
+        vcpu_id: usize,
+        pmu: PmuFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<()> {
+        let vcpu = self.vcpus.get_vcpu(vcpu_id)?;
+        let vpmu = vcpu.pmu();
+        let ret = match pmu {
+            PmuFunction::GetNumCounters => Ok(vpmu.num_counters()),
+            PmuFunction::GetCounterInfo(counter_index) => vpmu.counter_info(counter_index as usize),
+            PmuFunction::ConfigMatchingCounter {
+                counter_index,
+                counter_mask,
+                config_flags,
+                event_index,
+                event_data,
+            } => vpmu.config_matching(
+                counter_index as usize,
+                counter_mask as usize,
+                config_flags as usize,
+                event_index as usize,
+                event_data,
+            ),
+            PmuFunction::StartCounter {
+                counter_index,
+                counter_mask,
+                start_flags,
+                initial_value,
+            } => {
+                let mut snapshot = None;
+                if start_flags as usize & virt_pmu::start_flags::INIT_SNAPSHOT != 0 {
+                    snapshot = match vpmu.snapshot_shmem() {
+                        Some(shmem) => Some(read_pmu_snapshot(&self.vm_pages, shmem)?),
+                        None => {
+                            gprs.set_reg(GprIndex::A0, SBI_ERR_NO_SHMEM as usize);
+                            return Ok(());
+                        }
+                    };
+                }
+                vpmu.start(
+                    counter_index as usize,
+                    counter_mask as usize,
+                    start_flags as usize,
+                    initial_value,
+                    snapshot.as_ref(),
+                )
+                .map(|_| 0)
+            }
+            PmuFunction::StopCounter {
+                counter_index,
+                counter_mask,
+                stop_flags,
+            } => {
+                let shmem = vpmu.snapshot_shmem();
+                let take_snapshot = stop_flags as usize & virt_pmu::stop_flags::TAKE_SNAPSHOT != 0;
+                let mut snapshot = match shmem {
+                    Some(shmem) if take_snapshot => Some(read_pmu_snapshot(&self.vm_pages, shmem)?),
+                    None if take_snapshot => {
+                        gprs.set_reg(GprIndex::A0, SBI_ERR_NO_SHMEM as usize);
+                        return Ok(());
+                    }
+                    _ => None,
+                };
+                let ret = vpmu.stop(
+                    counter_index as usize,
+                    counter_mask as usize,
+                    stop_flags as usize,
+                    snapshot.as_mut(),
+                );
+                if let (Some(shmem), Some(snapshot)) = (shmem, snapshot) {
+                    write_pmu_snapshot(&self.vm_pages, shmem, &snapshot)?;
+                }
+                ret.map(|_| 0)
+            }
+            PmuFunction::ReadFirmwareCounter(counter_index) => vpmu
+                .fw_read(counter_index as usize)
+                .map(|value| value as usize),
+            // The counters are 64-bit wide on RV64, so the upper half is always zero.
+            PmuFunction::ReadFirmwareCounterHigh(counter_index) => {
+                vpmu.fw_read(counter_index as usize).map(|_| 0)
+            }
+            PmuFunction::SetSnapshotShmem {
+                shmem_lo,
+                shmem_hi,
+                flags,
+            } => {
+                let shmem_lo = shmem_lo as usize;
+                if shmem_lo == usize::MAX && shmem_hi as usize == usize::MAX {
+                    vpmu.set_snapshot_shmem(None);
+                    Ok(0)
+                } else if flags != 0 || shmem_hi != 0 || shmem_lo % PAGE_SIZE_4K != 0 {
+                    Err(SBI_ERR_INAVLID_PARAM)
+                } else if read_pmu_snapshot(&self.vm_pages, shmem_lo).is_err() {
+                    Err(SBI_ERR_INVALID_ADDRESS)
+                } else {
+                    vpmu.set_snapshot_shmem(Some(shmem_lo));
+                    Ok(0)
+                }
+            }
+        };
+        CSR.hcounteren
+            .write_value(FIXED_COUNTERS | vpmu.counter_enable_mask());
+
+        match ret {
+            Ok(value) => {
+                gprs.set_reg(GprIndex::A0, 0);
+                gprs.set_reg(GprIndex::A1, value);
+            }
+            Err(error) => gprs.set_reg(GprIndex::A0, error as usize),
+        }
+        Ok(())
+    }
+
+    /// Counts an occurrence of the firmware event `event` on the PMU of vCPU `vcpu_id`.
+    fn record_fw_event(&mut self, vcpu_id: usize, event: FirmwareEvent) {
+        if let Ok(vcpu) = self.vcpus.get_vcpu(vcpu_id) {
+            vcpu.pmu().record_fw_event(event);
+        }
+    }
+
+    /// Handles an SBI HSM call made by the vCPU `vcpu_id`. Returns whether the calling vCPU
+    /// resumes at the instruction after its ECALL. The other vCPUs are put while it runs, so
+    /// `hart_start` sets up the saved state of its target, which runs once the embedder runs it.
+    fn handle_hsm_function(
+        &mut self,
+        vcpu_id: usize,
+        hsm: HsmFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<bool> {
+        gprs.set_reg(GprIndex::A0, 0);
+        match hsm {
+            HsmFunction::HartStart {
+                hart_id,
+                start_addr,
+                opaque,
+            } => {
+                if !self.is_guest_memory(start_addr as usize) {
+                    gprs.set_reg(GprIndex::A0, SBI_ERR_INVALID_ADDRESS as usize);
+                    return Ok(true);
+                }
+                let Ok(vcpu) = self.vcpus.get_vcpu(hart_id as usize) else {
+                    gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize);
+                    return Ok(true);
+                };
+                if vcpu.status() != VmCpuStatus::PoweredOff {
+                    gprs.set_reg(GprIndex::A0, SBI_ERR_ALREADY_AVAILABLE as usize);
+                    return Ok(true);
+                }
+                vcpu.start(start_addr as usize, opaque as usize);
+                vcpu.set_status(VmCpuStatus::Runnable);
+                debug!("vCPU {} started at {:#x}", hart_id, start_addr);
+            }
+            HsmFunction::HartStop => {
+                let vcpu = self.vcpus.get_vcpu(vcpu_id)?;
+                vcpu.set_status(VmCpuStatus::PoweredOff);
+                debug!("vCPU {} stopped", vcpu_id);
+            }
+            HsmFunction::HartStatus { hart_id } => {
+                let Ok(vcpu) = self.vcpus.get_vcpu(hart_id as usize) else {
+                    gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize);
+                    return Ok(true);
+                };
+                let state = match vcpu.status() {
+                    VmCpuStatus::PoweredOff => HartState::Stopped,
+                    VmCpuStatus::Runnable | VmCpuStatus::Running => HartState::Started,
+                    VmCpuStatus::Suspended => HartState::Suspended,
+                };
+                gprs.set_reg(GprIndex::A1, state as usize);
+            }
+            HsmFunction::HartSuspend {
+                suspend_type,
+                resume_addr,
+                opaque,
+            } => match suspend_type {
+                // The vCPU resumes after the ECALL, with the call returning success.
+                HART_SUSPEND_TYPE_RETENTIVE => {
+                    self.vcpus
+                        .get_vcpu(vcpu_id)?
+                        .set_status(VmCpuStatus::Suspended);
+                    debug!("vCPU {} suspended", vcpu_id);
+                }
+                HART_SUSPEND_TYPE_NON_RETENTIVE => {
+                    if !self.is_guest_memory(resume_addr as usize) {
+                        gprs.set_reg(GprIndex::A0, SBI_ERR_INVALID_ADDRESS as usize);
+                        return Ok(true);
+                    }
+                    self.suspend_non_retentive(
+                        vcpu_id,
+                        resume_addr as usize,
+                        opaque as usize,
+                        gprs,
+                    )?;
+                    debug!("vCPU {} suspended, resuming at {:#x}", vcpu_id, resume_addr);
+                    return Ok(false);
+                }
+                _ => gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize),
+            },
+        }
+        Ok(true)
+    }
+
+    /// Handles an SBI SUSP call made by the vCPU `vcpu_id`. Returns whether the calling vCPU
+    /// resumes at the instruction after its ECALL, which it only does if the call fails.
+    fn handle_suspend_function(
+        &mut self,
+        vcpu_id: usize,
+        susp: SuspendFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<bool> {
+        let SuspendFunction::SystemSuspend {
+            sleep_type,
+            resume_addr,
+            opaque,
+        } = susp;
+        let error = if sleep_type != SLEEP_TYPE_SUSPEND_TO_RAM {
+            Some(SBI_ERR_INAVLID_PARAM)
+        } else if (0..VM_CPUS_MAX).any(|id| {
+            id != vcpu_id
+                && self
+                    .vcpus
+                    .get_vcpu(id)
+                    .map_or(false, |vcpu| vcpu.status() != VmCpuStatus::PoweredOff)
+        }) {
+            // All the other vCPUs must be stopped.
+            Some(SBI_ERR_DENIED)
+        } else if !self.is_guest_memory(resume_addr as usize) {
+            Some(SBI_ERR_INVALID_ADDRESS)
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            gprs.set_reg(GprIndex::A0, error as usize);
+            return Ok(true);
+        }
+        self.suspend_non_retentive(vcpu_id, resume_addr as usize, opaque as usize, gprs)?;
+        info!("VM suspended to RAM by vCPU {}", vcpu_id);
+        Ok(false)
+    }
+
+    /// Suspends the vCPU `vcpu_id`, running on this hart, so that it resumes at `resume_addr` with
+    /// `opaque` in a1, as if freshly started. `gprs` holds the vCPU's registers, which are
+    /// updated.
+    fn suspend_non_retentive(
+        &mut self,
+        vcpu_id: usize,
+        resume_addr: GuestPhysAddr,
+        opaque: usize,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<()> {
+        let vcpu = self.vcpus.get_vcpu(vcpu_id)?;
+        vcpu.restore_gprs(gprs);
+        vcpu.start_loaded(resume_addr, opaque);
+        vcpu.save_gprs(gprs);
+        vcpu.set_status(VmCpuStatus::Suspended);
+        Ok(())
+    }
+
+    /// Returns whether `gpa` is in a region of guest memory, where a vCPU can start executing.
+    fn is_guest_memory(&self, gpa: GuestPhysAddr) -> bool {
+        self.regions.find(gpa).map_or(false, |region| {
+            !matches!(
+                region.region_type(),
+                VmRegionType::Mmio | VmRegionType::Imsic | VmRegionType::Pci
+            )
+        })
+    }
+
+    /// Parks the suspended vCPU `vcpu_id`, loaded on this hart, until the next interrupt on the
+    /// hart, which is handled. Returns whether the vCPU has a virtual interrupt to take, and
+    /// resumes.
+    fn wait_for_wakeup(&mut self, vcpu_id: usize) -> HyperResult<bool> {
+        let vcpu = self.vcpus.get_vcpu(vcpu_id)?;
+        if vcpu.has_wakeup_irq() {
+            return Ok(true);
+        }
+
+        // The VS-level interrupts and the guest interrupt file's must end the `wfi` as well, but
+        // not be taken in HS-mode, so they are only enabled with `sstatus.SIE` clear.
+        let wake_irqs = traps::interrupt::VIRTUAL_SUPERVISOR_TIMER
+            | traps::interrupt::VIRTUAL_SUPERVISOR_EXTERNAL
+            | traps::interrupt::VIRTUAL_SUPERVISOR_SOFT
+            | traps::interrupt::SUPERVISOR_GUEST_EXTERNEL;
+        let guest_files = vcpu
+            .guest_interrupt_file()
+            .map_or(0, |file| 1 << file.guest_index);
+        unsafe {
+            let sie = riscv::register::sstatus::read().sie();
+            riscv::register::sstatus::clear_sie();
+            core::arch::asm!(
+                "csrs hgeie, {guest_files}",
+                "csrrs {hie}, hie, {wake_irqs}",
+                "wfi",
+                "csrw hie, {hie}",
+                "csrc hgeie, {guest_files}",
+                guest_files = in(reg) guest_files,
+                hie = out(reg) _,
+                wake_irqs = in(reg) wake_irqs,
+            );
+            if sie {
+                riscv::register::sstatus::set_sie();
+            }
+        }
+
+        // Handle what woke the hart, which may be an interrupt for the vCPU.
+        let sip = CSR.sip.get_value();
+        // With Sstc, or once the host's deadline passed, the timer interrupt is the host's and
+        // stays pending for it.
+        if !has_sstc() && sip & traps::interrupt::SUPERVISOR_TIMER != 0 {
+            self.handle_timer_irq(vcpu_id)?;
+        }
+        if sip & traps::interrupt::SUPERVISOR_EXTERNAL != 0 {
+            self.handle_irq(vcpu_id);
+        }
+        // Software interrupts are the host's, they stay pending for it once `run` returns.
+        Ok(self.vcpus.get_vcpu(vcpu_id)?.has_wakeup_irq())
+    }
+
+    fn handle_ipi_function(
+        &mut self,
+        vcpu_id: usize,
+        ipi: IpiFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<()> {
+        gprs.set_reg(GprIndex::A0, 0);
+        let (hart_mask, hart_mask_base) = match ipi {
+            IpiFunction::SendIpi {
+                hart_mask,
+                hart_mask_base,
+            } => (hart_mask as usize, hart_mask_base as usize),
+            IpiFunction::LegacySendIpi { hart_mask_addr } => {
+                let mut hart_mask = [0u8; core::mem::size_of::<usize>()];
+                if self
+                    .vm_pages
+                    .copy_from_guest(&mut hart_mask, hart_mask_addr as usize)
+                    .is_err()
+                {
+                    gprs.set_reg(GprIndex::A0, SBI_ERR_INVALID_ADDRESS as usize);
+                    return Ok(());
+                }
+                (usize::from_ne_bytes(hart_mask), 0)
+            }
+            IpiFunction::LegacyClearIpi => {
+                CSR.hvip
+                    .read_and_clear_bits(traps::interrupt::VIRTUAL_SUPERVISOR_SOFT);
+                return Ok(());
+            }
+        };
+        let Ok(targets) = self.vcpus_in_mask(hart_mask, hart_mask_base) else {
+            gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize);
+            return Ok(());
+        };
+
+        for target in targets {
+            self.vcpus
+                .get_vcpu(target)?
+                .inject_interrupt(traps::interrupt::VIRTUAL_SUPERVISOR_SOFT);
+        }
+        Ok(())
+    }
+
+    /// Returns the IDs of the vCPUs selected by an SBI `hart_mask`/`hart_mask_base` pair, with
+    /// `hart_mask_base` of -1 selecting every vCPU.
+    fn vcpus_in_mask(
+        &mut self,
+        hart_mask: usize,
+        hart_mask_base: usize,
+    ) -> HyperResult<ArrayVec<usize, VM_CPUS_MAX>> {
+        let mut vcpu_ids = ArrayVec::new();
+        if hart_mask_base == usize::MAX {
+            for vcpu_id in 0..VM_CPUS_MAX {
+                if self.vcpus.get_vcpu(vcpu_id).is_ok() {
+                    vcpu_ids.push(vcpu_id);
+                }
+            }
+            return Ok(vcpu_ids);
+        }
+        for bit in 0..usize::BITS as usize {
+            if hart_mask & (1 << bit) == 0 {
+                continue;
+            }
+            let vcpu_id = hart_mask_base
+                .checked_add(bit)
+                .ok_or(HyperError::InvalidParam)?;
+            self.vcpus
+                .get_vcpu(vcpu_id)
+                .map_err(|_| HyperError::InvalidParam)?;
+            vcpu_ids.push(vcpu_id);
+        }
+        Ok(vcpu_ids)
+    }
+
+    /// Handles an SBI RFNC call made by the vCPU `vcpu_id`. The calling vCPU is fenced right
+    /// away; the other targets get the fence queued, as a full flush of the VM's VS-stage entries
+    /// for SFENCE.VMA, and do it the next time they run, under the VM's VMID. The HFENCE
+    /// functions fail with SBI_ERR_NOT_SUPPORTED, as the guest doesn't see the H extension.
+    fn handle_rfnc_function(
+        &mut self,
+        vcpu_id: usize,
+        rfnc: RemoteFenceFunction,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<()> {
+        gprs.set_reg(GprIndex::A0, 0);
+        let (hart_mask, hart_mask_base, fence) = match rfnc {
+            RemoteFenceFunction::FenceI {
+                hart_mask,
+                hart_mask_base,
+            } => (hart_mask, hart_mask_base, vcpu::PENDING_FENCE_I),
+            RemoteFenceFunction::RemoteSFenceVMA {
+                hart_mask,
+                hart_mask_base,
+                ..
+            }
+            | RemoteFenceFunction::RemoteSFenceVMAWithASID {
+                hart_mask,
+                hart_mask_base,
+                ..
+            } => (hart_mask, hart_mask_base, vcpu::PENDING_HFENCE_VVMA),
+            _ => {
+                gprs.set_reg(GprIndex::A0, SBI_ERR_NOT_SUPPORTED as usize);
+                return Ok(());
+            }
+        };
+        let Ok(targets) = self.vcpus_in_mask(hart_mask as usize, hart_mask_base as usize) else {
+            gprs.set_reg(GprIndex::A0, SBI_ERR_INAVLID_PARAM as usize);
+            return Ok(());
+        };
+
+        for target in targets {
+            if target != vcpu_id {
+                self.vcpus.get_vcpu(target)?.queue_fences(fence);
+                continue;
+            }
+            // hgatp still holds the guest's VMID, so SFENCE.VMA becomes HFENCE.VVMA.
+            match rfnc {
+                RemoteFenceFunction::FenceI { .. } => unsafe { core::arch::asm!("fence.i") },
+                RemoteFenceFunction::RemoteSFenceVMA {
+                    start_addr, size, ..
+                } => local_hfence_vvma(start_addr as usize, size as usize, None),
+                RemoteFenceFunction::RemoteSFenceVMAWithASID {
+                    start_addr,
+                    size,
+                    asid,
+                    ..
+                } => local_hfence_vvma(start_addr as usize, size as usize, Some(asid as usize)),
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Performs the MMIO `access` on the register at `offset` of `device`, on the guest's `gprs`.
+fn emulate_mmio_access(
+    access: &MmioAccess,
+    device: &mut dyn MmioDevice,
+    offset: usize,
+    gprs: &mut GeneralPurposeRegisters,
+) -> HyperResult<()> {
+    let reg = GprIndex::from_raw(access.reg).unwrap();
+    let mut val = gprs.reg(reg);
+    access.emulate(device, offset, &mut val)?;
+    // A store leaves the value as it was, and writes to `zero` are dropped.
+    gprs.set_reg(reg, val);
+    Ok(())
+}
+
+/// The offset of the counter values in the PMU snapshot shared memory, after the overflow bitmap.
+const PMU_SNAPSHOT_VALUES_OFFSET: usize = 8;
+
+/// Reads the counter values from the PMU snapshot shared memory at `shmem`.
+fn read_pmu_snapshot(vm_pages: &VmPages, shmem: GuestPhysAddr) -> HyperResult<[u64; MAX_COUNTERS]> {
+    let mut bytes = [0u8; MAX_COUNTERS * 8];
+    vm_pages.copy_from_guest_phys(&mut bytes, shmem + PMU_SNAPSHOT_VALUES_OFFSET)?;
+    let mut values = [0u64; MAX_COUNTERS];
+    for (value, bytes) in values.iter_mut().zip(bytes.chunks_exact(8)) {
+        *value = u64::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    Ok(values)
+}
+
+/// Writes `values` to the counter values of the PMU snapshot shared memory at `shmem`.
+fn write_pmu_snapshot(
+    vm_pages: &VmPages,
+    shmem: GuestPhysAddr,
+    values: &[u64; MAX_COUNTERS],
+) -> HyperResult<()> {
+    let mut bytes = [0u8; MAX_COUNTERS * 8];
+    for (value, bytes) in values.iter().zip(bytes.chunks_exact_mut(8)) {
+        bytes.copy_from_slice(&value.to_ne_bytes());
+    }
+    vm_pages.copy_to_guest_phys(shmem + PMU_SNAPSHOT_VALUES_OFFSET, &bytes)
+}
+
+/// Raises the virtual timer interrupt of `vcpu`, running on this hart, if its emulated timer
+/// expired, and programs the hart's timer with the earliest of the timer's deadline and the
+/// host's.
+fn sync_vcpu_timer<H: HyperCraftHal>(vcpu: &mut VCpu<H>) {
+    let pcpu = PerCpu::<H>::this_cpu();
+    if pcpu
+        .timer()
+        .load(vcpu.timer(), riscv::register::time::read() as u64)
+    {
+        CSR.hvip
+            .read_and_set_bits(traps::interrupt::VIRTUAL_SUPERVISOR_TIMER);
+    }
+    pcpu.program_timer();
+}
+
+/// Reads the CSR `csr` on behalf of a guest that trapped accessing it. `user_counters` is the
+/// guest's `scounteren` if the access came from VU-mode.
+fn read_virtual_csr(csr: u16, user_counters: Option<usize>) -> HyperResult<usize> {
+    match csr {
+        CSR_CYCLE..=CSR_HPMCOUNTER31 => {
+            if let Some(scounteren) = user_counters {
+                if scounteren & (1 << (csr - CSR_CYCLE)) == 0 {
+                    return Err(HyperError::InvalidInstruction);
+                }
+            }
+            let val = match csr {
+                CSR_CYCLE => riscv::register::cycle::read(),
+                CSR_TIME => {
+                    let htimedelta: usize;
+                    unsafe { core::arch::asm!("csrr {}, htimedelta", out(reg) htimedelta) };
+                    riscv::register::time::read().wrapping_add(htimedelta)
+                }
+                CSR_INSTRET => riscv::register::instret::read(),
+                // The hardware performance counters are not virtualized.
+                _ => 0,
+            };
+            Ok(val)
+        }
+        CSR_SATP if user_counters.is_none() => {
+            let vsatp: usize;
+            unsafe { core::arch::asm!("csrr {}, vsatp", out(reg) vsatp) };
+            Ok(vsatp)
+        }
+        // Everything else, e.g. the hypervisor CSRs, doesn't exist for the guest.
+        _ => Err(HyperError::InvalidInstruction),
+    }
+}
+
+/// Writes `val` to the CSR `csr` on behalf of a guest that trapped accessing it.
+fn write_virtual_csr(csr: u16, is_user: bool, val: usize) -> HyperResult<()> {
+    match csr {
+        CSR_SATP if !is_user => {
+            unsafe { core::arch::asm!("csrw vsatp, {}", in(reg) val) };
+            Ok(())
+        }
+        // The counters are read-only.
+        _ => Err(HyperError::InvalidInstruction),
+    }
+}
+
+/// Flushes this hart's VS-stage TLB entries for the guest virtual range `[start, start + size)`,
+/// limited to `asid` if given. Only entries of the VMID currently in `hgatp` are affected.
+fn local_hfence_vvma(start: usize, size: usize, asid: Option<usize>) {
+    use core::arch::riscv64::{hfence_vvma, hfence_vvma_all, hfence_vvma_asid, hfence_vvma_vaddr};
+    // Ranges larger than this are cheaper to flush as a whole.
+    const MAX_FLUSH_PAGES: usize = 64;
+
+    let flush_all =
+        (start == 0 && size == 0) || size == usize::MAX || size / PAGE_SIZE_4K > MAX_FLUSH_PAGES;
+    unsafe {
+        match (flush_all, asid) {
+            (true, None) => hfence_vvma_all(),
+            (true, Some(asid)) => hfence_vvma_asid(asid),
+            (false, asid) => {
+                for vaddr in (start..start.saturating_add(size)).step_by(PAGE_SIZE_4K) {
+                    match asid {
+                        Some(asid) => hfence_vvma(vaddr, asid),
+                        None => hfence_vvma_vaddr(vaddr),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,69 +1,403 @@
-use core::arch::global_asm;
-
-use arrayvec::ArrayVec;
-use riscv_decode::Instruction;
-
-use crate::{GuestPhysAddr, HyperError, HyperResult};
-global_asm!(include_str!("mem_extable.S"));
-
-extern "C" {
-    fn _copy_to_guest(dest_gpa: usize, src: *const u8, len: usize) -> usize;
-    fn _copy_from_guest(dest: *mut u8, src_gpa: usize, len: usize) -> usize;
-    fn _fetch_guest_instruction(gva: usize, raw_inst: *mut u32) -> isize;
-}
-
-// Types of regions in a VM's guest physical address space.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum VmRegionType {
-    // Memory that is private to this VM.
-    Confidential,
-    // Memory that is shared with the parent
-    Shared,
-    // Emulated MMIO region; accesses always cause a fault that is forwarded to the VM's host.
-    Mmio,
-    // IMSIC interrupt file pages.
-    Imsic,
-    // PCI BAR pages.
-    Pci,
-    // Memory that is private to this VM and marked removable.
-    ConfidentialRemovable,
-    // Memory that is shared with the host and marked removable.
-    SharedRemovable,
-}
-
-/// A contiguous region of guest physical address space.
-#[derive(Clone, Debug)]
-pub struct VmRegion {
-    start: GuestPhysAddr,
-    end: GuestPhysAddr,
-    region_type: VmRegionType,
-}
-
-/// The maximum number of distinct memory regions we support in `VmRegionList`.
-const MAX_MEM_REGIONS: usize = 128;
-
-/// The regions of guest physical address space for a VM. Used to track which parts of the address
-/// space are designated for a particular purpose. Pages may only be inserted into a VM's address
-/// space if the mapping falls within a region of the proper type.
-pub struct VmRegionList {
-    regions: ArrayVec<VmRegion, MAX_MEM_REGIONS>,
-}
-
-/// Represents the activate VM address space. Used to directly access a guest's memory.
-#[derive(Default)]
-pub struct VmPages;
-
-impl VmPages {
-    /// Fetches and decodes the instruction at `pc` in the guest's virtual address.
-    pub fn fetch_guest_instruction(&self, pc: GuestPhysAddr) -> HyperResult<u32> {
-        let mut raw_inst = 0u32;
-        // Safety: _fetch_guest_instruction internally detects and handles an invalid guest virtual
-        // address in `pc' and will only write up to 4 bytes to `raw_inst`.
-        let ret = unsafe { _fetch_guest_instruction(pc, &mut raw_inst) };
-        if ret < 0 {
-            return Err(HyperError::FetchFault);
-        }
-        // let inst = riscv_decode::decode(raw_inst).map_err(|_| HyperError::DecodeError)?;
-        Ok(raw_inst)
-    }
-}
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+use arrayvec::ArrayVec;
+use riscv_decode::Instruction;
+
+use crate::{memory::PAGE_SIZE_4K, GuestPhysAddr, GuestVirtAddr, HyperError, HyperResult};
+global_asm!(include_str!("mem_extable.S"));
+
+extern "C" {
+    fn _copy_to_guest(dest_gpa: usize, src: *const u8, len: usize) -> usize;
+    fn _copy_from_guest(dest: *mut u8, src_gpa: usize, len: usize) -> usize;
+    fn _fetch_guest_instruction(gva: usize, raw_inst: *mut u32) -> isize;
+}
+
+/// Types of regions in a VM's guest physical address space.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VmRegionType {
+    /// Memory that is private to this VM.
+    Confidential,
+    /// Memory that is shared with the parent
+    Shared,
+    /// Emulated MMIO region; accesses always cause a fault that is forwarded to the VM's host.
+    Mmio,
+    /// IMSIC interrupt file pages.
+    Imsic,
+    /// PCI BAR pages.
+    Pci,
+    /// Memory that is private to this VM and marked removable.
+    ConfidentialRemovable,
+    /// Memory that is shared with the host and marked removable.
+    SharedRemovable,
+}
+
+impl VmRegionType {
+    /// Returns whether pages may be mapped in regions of this type. MMIO regions must fault in
+    /// the G-stage so that their accesses get emulated.
+    pub fn is_mappable(self) -> bool {
+        self != Self::Mmio
+    }
+
+    /// Returns whether regions of this type may be removed from the address space.
+    pub fn is_removable(self) -> bool {
+        matches!(self, Self::ConfidentialRemovable | Self::SharedRemovable)
+    }
+}
+
+/// A contiguous region of guest physical address space.
+#[derive(Clone, Debug)]
+pub struct VmRegion {
+    start: GuestPhysAddr,
+    end: GuestPhysAddr,
+    region_type: VmRegionType,
+}
+
+impl VmRegion {
+    /// Returns the first address of the region.
+    pub fn start(&self) -> GuestPhysAddr {
+        self.start
+    }
+
+    /// Returns the address right after the region.
+    pub fn end(&self) -> GuestPhysAddr {
+        self.end
+    }
+
+    /// Returns the type of the region.
+    pub fn region_type(&self) -> VmRegionType {
+        self.region_type
+    }
+
+    /// Returns whether `[start, end)` lies within the region.
+    fn contains_range(&self, start: GuestPhysAddr, end: GuestPhysAddr) -> bool {
+        self.start <= start && end <= self.end
+    }
+}
+
+/// The maximum number of distinct memory regions we support in `VmRegionList`.
+const MAX_MEM_REGIONS: usize = 128;
+
+/// The regions of guest physical address space for a VM. Used to track which parts of the address
+/// space are designated for a particular purpose. Pages may only be inserted into a VM's address
+/// space if the mapping falls within a region of the proper type.
+#[derive(Default)]
+pub struct VmRegionList {
+    // Sorted by start address, never overlapping.
+    regions: ArrayVec<VmRegion, MAX_MEM_REGIONS>,
+}
+
+impl VmRegionList {
+    /// Creates an empty region list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Designates `[start, start + size)` for `region_type`. Fails if the range overlaps an
+    /// existing region.
+    pub fn add(
+        &mut self,
+        start: GuestPhysAddr,
+        size: usize,
+        region_type: VmRegionType,
+    ) -> HyperResult<()> {
+        let end = start.checked_add(size).ok_or(HyperError::InvalidParam)?;
+        if size == 0 {
+            return Err(HyperError::InvalidParam);
+        }
+        let index = self.regions.partition_point(|r| r.start < start);
+        let overlaps_prev = index > 0 && self.regions[index - 1].end > start;
+        let overlaps_next = self.regions.get(index).map_or(false, |r| r.start < end);
+        if overlaps_prev || overlaps_next {
+            return Err(HyperError::InvalidParam);
+        }
+        self.regions
+            .try_insert(
+                index,
+                VmRegion {
+                    start,
+                    end,
+                    region_type,
+                },
+            )
+            .map_err(|_| HyperError::NoMemory)
+    }
+
+    /// Removes the region starting at `start` and returns it.
+    pub fn remove(&mut self, start: GuestPhysAddr) -> HyperResult<VmRegion> {
+        let index = self
+            .regions
+            .iter()
+            .position(|r| r.start == start)
+            .ok_or(HyperError::NotFound)?;
+        Ok(self.regions.remove(index))
+    }
+
+    /// Returns the region containing `addr`, if any.
+    pub fn find(&self, addr: GuestPhysAddr) -> Option<&VmRegion> {
+        let index = self.regions.partition_point(|r| r.start <= addr);
+        self.regions[..index].last().filter(|r| addr < r.end)
+    }
+
+    /// Checks that pages may be mapped at `[start, start + size)`: the range must lie within a
+    /// single region of a mappable type.
+    pub fn check_mapping(&self, start: GuestPhysAddr, size: usize) -> HyperResult<VmRegionType> {
+        let end = start.checked_add(size).ok_or(HyperError::InvalidParam)?;
+        match self.find(start) {
+            Some(region)
+                if region.contains_range(start, end) && region.region_type.is_mappable() =>
+            {
+                Ok(region.region_type)
+            }
+            _ => Err(HyperError::InvalidParam),
+        }
+    }
+}
+
+/// Represents the activate VM address space. Used to directly access a guest's memory.
+#[derive(Default)]
+pub struct VmPages;
+
+impl VmPages {
+    /// Fetches and decodes the instruction at `pc` in the guest's virtual address.
+    pub fn fetch_guest_instruction(&self, pc: GuestPhysAddr) -> HyperResult<u32> {
+        let mut raw_inst = 0u32;
+        // Safety: _fetch_guest_instruction internally detects and handles an invalid guest virtual
+        // address in `pc' and will only write up to 4 bytes to `raw_inst`.
+        let ret = unsafe { _fetch_guest_instruction(pc, &mut raw_inst) };
+        if ret < 0 {
+            return Err(HyperError::FetchFault);
+        }
+        // let inst = riscv_decode::decode(raw_inst).map_err(|_| HyperError::DecodeError)?;
+        Ok(raw_inst)
+    }
+
+    /// Copies `dest.len()` bytes from the guest's virtual address `src` into `dest`.
+    pub fn copy_from_guest(&self, dest: &mut [u8], src: GuestVirtAddr) -> HyperResult<()> {
+        // Safety: _copy_from_guest internally detects and handles an invalid guest address in
+        // `src` and will only write up to `dest.len()` bytes to `dest`.
+        let copied = unsafe { _copy_from_guest(dest.as_mut_ptr(), src, dest.len()) };
+        if copied != dest.len() {
+            return Err(HyperError::PageFault);
+        }
+        Ok(())
+    }
+
+    /// Copies `src` to the guest's virtual address `dest`.
+    pub fn copy_to_guest(&self, dest: GuestVirtAddr, src: &[u8]) -> HyperResult<()> {
+        // Safety: _copy_to_guest internally detects and handles an invalid guest address in
+        // `dest` and will only read up to `src.len()` bytes from `src`.
+        let copied = unsafe { _copy_to_guest(dest, src.as_ptr(), src.len()) };
+        if copied != src.len() {
+            return Err(HyperError::PageFault);
+        }
+        Ok(())
+    }
+
+    /// Copies `dest.len()` bytes from the guest physical address `src` into `dest`.
+    pub fn copy_from_guest_phys(&self, dest: &mut [u8], src: GuestPhysAddr) -> HyperResult<()> {
+        with_bare_vsatp(|| self.copy_from_guest(dest, src))
+    }
+
+    /// Copies `src` to the guest physical address `dest`.
+    pub fn copy_to_guest_phys(&self, dest: GuestPhysAddr, src: &[u8]) -> HyperResult<()> {
+        with_bare_vsatp(|| self.copy_to_guest(dest, src))
+    }
+}
+
+/// Types that can be copied from and to guest memory as raw bytes.
+///
+/// # Safety
+///
+/// Any bit pattern must be a valid value of the type, and the type must have no padding.
+pub unsafe trait ByteValued: Copy {}
+
+macro_rules! impl_byte_valued {
+    ($($t:ty),*) => {
+        $(unsafe impl ByteValued for $t {})*
+    };
+}
+
+impl_byte_valued!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+unsafe impl<T: ByteValued, const N: usize> ByteValued for [T; N] {}
+
+/// The memory of a VM, as seen from the hypervisor through the VM's G-stage page table and, for
+/// guest virtual addresses, the VS-stage page table of one of its vCPUs. Accesses to addresses
+/// the guest can't access fail with `HyperError::PageFault`.
+///
+/// Obtained from `VM::guest_phys_memory` or `VM::guest_virt_memory`, and only usable on the CPU
+/// it was obtained on.
+pub struct GuestMemory<'a> {
+    hgatp: usize,
+    // Bare for guest physical addresses.
+    vsatp: usize,
+    // Borrows the VM so that it doesn't run, and isn't sent to another CPU, meanwhile.
+    marker: PhantomData<&'a mut *const ()>,
+}
+
+impl<'a> GuestMemory<'a> {
+    /// Creates an accessor for the memory translated by `hgatp` and `vsatp`. The VMID in `hgatp`
+    /// must be valid on this CPU.
+    pub(crate) fn new(hgatp: usize, vsatp: usize) -> Self {
+        Self {
+            hgatp,
+            vsatp,
+            marker: PhantomData,
+        }
+    }
+
+    /// Copies `dest.len()` bytes from the guest address `src` into `dest`.
+    pub fn read_bytes(&self, src: usize, dest: &mut [u8]) -> HyperResult<()> {
+        self.with_guest_translation(|vm_pages| vm_pages.copy_from_guest(dest, src))
+    }
+
+    /// Copies `src` to the guest address `dest`.
+    pub fn write_bytes(&self, dest: usize, src: &[u8]) -> HyperResult<()> {
+        self.with_guest_translation(|vm_pages| vm_pages.copy_to_guest(dest, src))
+    }
+
+    /// Reads a `T` at the guest address `src`.
+    pub fn read_obj<T: ByteValued>(&self, src: usize) -> HyperResult<T> {
+        let mut obj = MaybeUninit::<T>::uninit();
+        // Safety: the slice covers `obj`, and any bytes written to it make a valid `T`.
+        let bytes =
+            unsafe { core::slice::from_raw_parts_mut(obj.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        self.read_bytes(src, bytes)?;
+        Ok(unsafe { obj.assume_init() })
+    }
+
+    /// Writes `obj` to the guest address `dest`.
+    pub fn write_obj<T: ByteValued>(&self, dest: usize, obj: &T) -> HyperResult<()> {
+        // Safety: `T` has no padding, so all its bytes are initialized.
+        let bytes =
+            unsafe { core::slice::from_raw_parts(obj as *const T as *const u8, size_of::<T>()) };
+        self.write_bytes(dest, bytes)
+    }
+
+    /// Reads the NUL-terminated UTF-8 string at the guest address `src`, of at most `max_len`
+    /// bytes without the NUL. Fails with `HyperError::OutOfRange` if it's longer.
+    pub fn read_c_str(&self, src: usize, max_len: usize) -> HyperResult<String> {
+        let mut bytes = Vec::new();
+        let mut addr = src;
+        loop {
+            // Read up to the end of the page at most, the string may end before a page the guest
+            // can't access.
+            let chunk_len = (PAGE_SIZE_4K - addr % PAGE_SIZE_4K).min(max_len + 1 - bytes.len());
+            let start = bytes.len();
+            bytes.resize(start + chunk_len, 0);
+            self.read_bytes(addr, &mut bytes[start..])?;
+            if let Some(len) = bytes[start..].iter().position(|&b| b == 0) {
+                bytes.truncate(start + len);
+                return String::from_utf8(bytes).map_err(|_| HyperError::DecodeError);
+            }
+            if bytes.len() > max_len {
+                return Err(HyperError::OutOfRange);
+            }
+            addr += chunk_len;
+        }
+    }
+
+    /// Runs `f` with the guest's translation loaded in `hgatp` and `vsatp`, and the HLV/HSV
+    /// accesses done as from VS-mode.
+    fn with_guest_translation<T>(&self, f: impl FnOnce(&VmPages) -> T) -> T {
+        const HSTATUS_SPVP: usize = 1 << 8;
+        let (hgatp, vsatp, hstatus): (usize, usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "csrrw {hgatp}, hgatp, {new_hgatp}",
+                "csrrw {vsatp}, vsatp, {new_vsatp}",
+                "csrrs {hstatus}, hstatus, {spvp}",
+                new_hgatp = in(reg) self.hgatp,
+                new_vsatp = in(reg) self.vsatp,
+                spvp = in(reg) HSTATUS_SPVP,
+                hgatp = out(reg) hgatp,
+                vsatp = out(reg) vsatp,
+                hstatus = out(reg) hstatus,
+            );
+        }
+        let ret = f(&VmPages);
+        unsafe {
+            core::arch::asm!(
+                "csrw hgatp, {hgatp}",
+                "csrw vsatp, {vsatp}",
+                "csrw hstatus, {hstatus}",
+                hgatp = in(reg) hgatp,
+                vsatp = in(reg) vsatp,
+                hstatus = in(reg) hstatus,
+            );
+        }
+        ret
+    }
+}
+
+/// Runs `f` with VS-stage translation off, so that the guest accessors take guest physical
+/// addresses.
+fn with_bare_vsatp<T>(f: impl FnOnce() -> T) -> T {
+    let vsatp: usize;
+    unsafe { core::arch::asm!("csrrw {}, vsatp, zero", out(reg) vsatp) };
+    let ret = f();
+    unsafe { core::arch::asm!("csrw vsatp, {}", in(reg) vsatp) };
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_reject_overlaps() {
+        let mut regions = VmRegionList::new();
+        regions
+            .add(0x8000_0000, 0x1000_0000, VmRegionType::Confidential)
+            .unwrap();
+        regions
+            .add(0x1000_0000, 0x1000, VmRegionType::Mmio)
+            .unwrap();
+        assert!(regions
+            .add(0x8fff_f000, 0x2000, VmRegionType::Shared)
+            .is_err());
+        assert!(regions
+            .add(0x0fff_f000, 0x2000, VmRegionType::Shared)
+            .is_err());
+        assert!(regions.add(0x1000_1000, 0, VmRegionType::Shared).is_err());
+        regions
+            .add(0x1000_1000, 0x1000, VmRegionType::Shared)
+            .unwrap();
+
+        assert_eq!(
+            regions.find(0x1000_0fff).map(|r| r.region_type()),
+            Some(VmRegionType::Mmio)
+        );
+        assert!(regions.find(0x1000_2000).is_none());
+        assert_eq!(
+            regions.remove(0x1000_1000).unwrap().region_type(),
+            VmRegionType::Shared
+        );
+        assert!(regions.find(0x1000_1000).is_none());
+    }
+
+    #[test]
+    fn mappings_must_fit_a_mappable_region() {
+        let mut regions = VmRegionList::new();
+        regions
+            .add(0x8000_0000, 0x2000, VmRegionType::Confidential)
+            .unwrap();
+        regions
+            .add(0x8000_2000, 0x1000, VmRegionType::Shared)
+            .unwrap();
+        regions
+            .add(0x1000_0000, 0x1000, VmRegionType::Mmio)
+            .unwrap();
+        assert_eq!(
+            regions.check_mapping(0x8000_1000, 0x1000),
+            Ok(VmRegionType::Confidential)
+        );
+        assert!(regions.check_mapping(0x8000_1000, 0x2000).is_err());
+        assert!(regions.check_mapping(0x1000_0000, 0x1000).is_err());
+        assert!(regions.check_mapping(0x9000_0000, 0x1000).is_err());
+    }
+}
@@ -1,57 +1,108 @@
-use riscv::register::mcause::Interrupt;
-
-use crate::{GuestPhysAddr, GuestVirtAddr};
-use tock_registers::LocalRegisterCopy;
-
-use super::{csrs::defs::hstatus, sbi::SbiMessage};
-
-/// The privilege level at the time a trap occurred, as reported in sstatus.SPP or hstatus.SPVP.
-#[derive(Copy, Clone, Debug)]
-#[repr(u64)]
-pub enum PrivilegeLevel {
-    User = 0,
-    Supervisor = 1,
-}
-
-impl PrivilegeLevel {
-    pub fn from_hstatus(csr: usize) -> Self {
-        let val = LocalRegisterCopy::<usize, hstatus::Register>::new(csr);
-        match val.read(hstatus::spvp) {
-            0 => Self::User,
-            1 => Self::Supervisor,
-            _ => unreachable!(), // Field is only 1-bit wide.
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-/// Identifies the reason for a trap taken from a vCPU.
-pub enum VmExitInfo {
-    /// ECALLs from VS mode.
-    Ecall(Option<SbiMessage>),
-    /// G-stage page faluts
-    PageFault {
-        /// Page fault addr.
-        fault_addr: GuestPhysAddr,
-        /// Page fault inst addr.
-        falut_pc: GuestVirtAddr,
-        /// Page fault inst.
-        inst: u32,
-        /// Page fault privilege level.
-        priv_level: PrivilegeLevel,
-    },
-    /// Instruction emulation trap
-    VirtualInstruction {
-        /// Virtual instruction addr.
-        fault_pc: GuestVirtAddr,
-        /// Virtual instruction privilege level.
-        priv_level: PrivilegeLevel,
-    },
-    /// An interrupt intended for the vCPU's host.
-    HostInterruot(Interrupt),
-    /// An timer interrupt for the running vCPU that can't be delegated and must be injected. The
-    /// interrupt is injected the vCPU is run.
-    TimerInterruptEmulation,
-    /// An external interrupt for the running vCPU that can't be delegated and must be injected.
-    ExternalInterruptEmulation,
-}
+use riscv::register::mcause::Interrupt;
+
+use crate::{GuestPhysAddr, GuestVirtAddr, HyperResult};
+use tock_registers::LocalRegisterCopy;
+
+use super::{csrs::defs::hstatus, regs::GeneralPurposeRegisters, sbi::SbiMessage};
+
+/// The privilege level at the time a trap occurred, as reported in sstatus.SPP or hstatus.SPVP.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u64)]
+pub enum PrivilegeLevel {
+    User = 0,
+    Supervisor = 1,
+}
+
+impl PrivilegeLevel {
+    pub fn from_hstatus(csr: usize) -> Self {
+        let val = LocalRegisterCopy::<usize, hstatus::Register>::new(csr);
+        match val.read(hstatus::spvp) {
+            0 => Self::User,
+            1 => Self::Supervisor,
+            _ => unreachable!(), // Field is only 1-bit wide.
+        }
+    }
+}
+
+/// The kind of guest access that caused a G-stage page fault.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GuestAccessType {
+    /// An instruction fetch.
+    Fetch,
+    /// A load.
+    Load,
+    /// A store or AMO.
+    Store,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Identifies the reason for a trap taken from a vCPU.
+pub enum VmExitInfo {
+    /// ECALLs from VS mode.
+    Ecall(Option<SbiMessage>),
+    /// G-stage page faluts
+    PageFault {
+        /// Page fault addr.
+        fault_addr: GuestPhysAddr,
+        /// Page fault inst addr.
+        falut_pc: GuestVirtAddr,
+        /// The guest virtual address of the access, or 0 if `stval` did not report one.
+        fault_vaddr: GuestVirtAddr,
+        /// Page fault inst.
+        inst: u32,
+        /// The kind of access that faulted.
+        access: GuestAccessType,
+        /// Page fault privilege level.
+        priv_level: PrivilegeLevel,
+    },
+    /// Instruction emulation trap
+    VirtualInstruction {
+        /// Virtual instruction addr.
+        fault_pc: GuestVirtAddr,
+        /// The trapping instruction, or 0 if `stval` did not report it.
+        inst: u32,
+        /// Virtual instruction privilege level.
+        priv_level: PrivilegeLevel,
+    },
+    /// A synchronous exception that is not delegated to VS-mode and the hypervisor does not
+    /// handle itself.
+    GuestException {
+        /// The exception, one of `traps::exception`.
+        cause: usize,
+        /// The trap value reported in `stval`.
+        tval: usize,
+    },
+    /// An interrupt intended for the vCPU's host.
+    HostInterruot(Interrupt),
+    /// An timer interrupt for the running vCPU that can't be delegated and must be injected. The
+    /// interrupt is injected the vCPU is run.
+    TimerInterruptEmulation,
+    /// An external interrupt for the running vCPU that can't be delegated and must be injected.
+    ExternalInterruptEmulation,
+}
+
+/// What the VM does with an exit after a [`VmExitHandler`] has seen it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmExitAction {
+    /// The exit is left to the hypervisor's built-in handling.
+    Default,
+    /// The exit was handled, the vCPU resumes at the trapping instruction.
+    Resume,
+    /// The exit was handled, the vCPU resumes after the trapping instruction of the given
+    /// length.
+    Advance(usize),
+}
+
+/// Embedder hooks called on every exit of the vCPUs of a VM, before the built-in handling. They
+/// allow adding hypercalls and device models, or overriding the handling of SBI calls, faults and
+/// interrupts.
+pub trait VmExitHandler {
+    /// Handles `exit_info` of vCPU `vcpu_id`. `gprs` are the registers of the vCPU, which it
+    /// resumes with once the exit is handled.
+    fn handle_exit(
+        &mut self,
+        vcpu_id: usize,
+        exit_info: &VmExitInfo,
+        gprs: &mut GeneralPurposeRegisters,
+    ) -> HyperResult<VmExitAction>;
+}
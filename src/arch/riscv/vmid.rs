@@ -0,0 +1,97 @@
+//! VMID allocation. VMIDs are handed out from a global counter; once they run out, a new
+//! generation starts, all VMs get a new VMID the next time they run and each hart flushes its
+//! whole G-stage TLB once before running a VM in the new generation.
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use super::ept::GStageMode;
+
+/// The shift of the VMID field of `hgatp`.
+pub const HGATP_VMID_SHIFT: usize = 44;
+/// The VMID field of `hgatp`.
+pub const HGATP_VMID_MASK: usize = 0x3fff << HGATP_VMID_SHIFT;
+
+/// The number of VMID bits implemented by all harts.
+static VMID_BITS: AtomicUsize = AtomicUsize::new(14);
+/// The current generation.
+static VMID_GENERATION: AtomicU64 = AtomicU64::new(1);
+/// The next VMID to hand out in the current generation.
+static NEXT_VMID: Mutex<usize> = Mutex::new(1);
+
+/// Detects VMIDLEN on this hart, by writing all ones to the VMID field of `hgatp` and reading back
+/// which bits stuck. The other fields are unspecified with MODE=Bare, so the probe uses a
+/// supported translating mode with a zero PPN; nothing runs with V=1 meanwhile, and the G-stage
+/// TLB is flushed before `hgatp` is cleared again. Must be called on every hart, after
+/// `detect_gstage_modes`, before any VM runs.
+pub(crate) fn detect_vmid_bits() {
+    let Some(mode) = [GStageMode::Sv39x4, GStageMode::Sv48x4, GStageMode::Sv57x4]
+        .into_iter()
+        .find(|mode| mode.is_supported())
+    else {
+        // No VM can run on this hart anyway.
+        return;
+    };
+    let hgatp: usize;
+    unsafe {
+        core::arch::asm!(
+            "csrw hgatp, {probe}",
+            "csrr {hgatp}, hgatp",
+            probe = in(reg) mode.hgatp(0) | HGATP_VMID_MASK,
+            hgatp = out(reg) hgatp,
+        );
+        core::arch::riscv64::hfence_gvma_all();
+        core::arch::asm!("csrw hgatp, zero");
+    }
+    let bits = ((hgatp & HGATP_VMID_MASK) >> HGATP_VMID_SHIFT).count_ones() as usize;
+    VMID_BITS.fetch_min(bits, Ordering::Relaxed);
+}
+
+/// The VMID of a VM, valid in the generation it was allocated in.
+#[derive(Default)]
+pub struct Vmid {
+    generation: u64,
+    vmid: usize,
+}
+
+impl Vmid {
+    /// Creates a VMID that gets allocated the first time the VM runs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the VMID to run the VM with on this hart, allocating a new one if it's from an old
+    /// generation. `hart_generation` is the generation this hart last flushed its G-stage TLB
+    /// for; the whole G-stage TLB is flushed if it's behind.
+    ///
+    /// Without VMIDs, every VM uses VMID 0 and every allocation starts a new generation, so
+    /// switching between VMs flushes the G-stage TLB.
+    pub fn activate(&mut self, hart_generation: &mut u64) -> usize {
+        if self.generation != VMID_GENERATION.load(Ordering::Acquire) {
+            let mut next_vmid = NEXT_VMID.lock();
+            let mut generation = VMID_GENERATION.load(Ordering::Relaxed);
+            if self.generation != generation {
+                let bits = VMID_BITS.load(Ordering::Relaxed);
+                if *next_vmid >= 1 << bits {
+                    generation += 1;
+                    VMID_GENERATION.store(generation, Ordering::Release);
+                    // VMID 0 is left for the host unless it's the only one.
+                    *next_vmid = if bits == 0 { 0 } else { 1 };
+                }
+                self.generation = generation;
+                self.vmid = *next_vmid;
+                *next_vmid += 1;
+            }
+        }
+        if *hart_generation != self.generation {
+            unsafe { core::arch::riscv64::hfence_gvma_all() };
+            *hart_generation = self.generation;
+        }
+        self.vmid
+    }
+
+    /// Returns the VMID, which is only meaningful once the VM has run.
+    pub fn vmid(&self) -> usize {
+        self.vmid
+    }
+}
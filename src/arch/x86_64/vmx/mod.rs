@@ -1,12 +1,12 @@
-mod definitions;
-mod detect;
-mod percpu;
-mod region;
-mod vcpu;
-mod vmcs;
-
-pub use detect::has_hardware_support;
-pub use percpu::VmxPerCpuState;
-pub use vcpu::VmxVcpu;
-pub use definitions::VmxExitReason;
-pub use vmcs::VmxExitInfo;
+mod definitions;
+mod detect;
+mod percpu;
+mod region;
+mod vcpu;
+mod vmcs;
+
+pub use detect::has_hardware_support;
+pub use percpu::VmxPerCpuState;
+pub use vcpu::VmxVcpu;
+pub use definitions::VmxExitReason;
+pub use vmcs::VmxExitInfo;
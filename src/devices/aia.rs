@@ -0,0 +1,471 @@
+//! Emulated AIA devices: an IMSIC interrupt file, and an APLIC domain in MSI delivery mode
+//! forwarding wired interrupts to the interrupt files as MSIs.
+use alloc::vec::Vec;
+
+use super::MmioDevice;
+use crate::{HyperError, HyperResult};
+
+/// Size of the MSI page of an IMSIC interrupt file.
+pub const IMSIC_PAGE_SIZE: usize = 0x1000;
+/// Maximum number of interrupt identities of an emulated interrupt file. Identity 0 is reserved
+/// and never raised.
+pub const IMSIC_MAX_IDS: usize = 2048;
+/// Number of interrupt sources for the APLIC. Source 0 is reserved and never raised.
+pub const APLIC_MAX_SOURCES: usize = 1024;
+/// Size of the register space of an APLIC domain.
+pub const APLIC_SIZE: usize = 0x4000;
+
+// `siselect` values of the interrupt file registers.
+const ISELECT_EIDELIVERY: usize = 0x70;
+const ISELECT_EITHRESHOLD: usize = 0x72;
+const ISELECT_EIP0: usize = 0x80;
+const ISELECT_EIP63: usize = 0xbf;
+const ISELECT_EIE0: usize = 0xc0;
+const ISELECT_EIE63: usize = 0xff;
+
+/// Offset of the little-endian `seteipnum` register in the MSI page of an interrupt file.
+pub const SETEIPNUM_LE: usize = 0x0;
+// And of the big-endian one.
+const SETEIPNUM_BE: usize = 0x4;
+
+// Register windows of the APLIC, as offsets from its base.
+const DOMAINCFG: usize = 0x0;
+const SOURCECFG_BASE: usize = 0x4;
+const SETIP_BASE: usize = 0x1c00;
+const SETIPNUM: usize = 0x1cdc;
+const IN_CLRIP_BASE: usize = 0x1d00;
+const CLRIPNUM: usize = 0x1ddc;
+const SETIE_BASE: usize = 0x1e00;
+const SETIENUM: usize = 0x1edc;
+const CLRIE_BASE: usize = 0x1f00;
+const CLRIENUM: usize = 0x1fdc;
+const SETIPNUM_LE: usize = 0x2000;
+const SETIPNUM_BE: usize = 0x2004;
+const GENMSI: usize = 0x3000;
+const TARGET_BASE: usize = 0x3004;
+
+// `domaincfg` bits. Bits 31-24 read as 0x80, and only MSI delivery mode is implemented.
+const DOMAINCFG_FIXED: u32 = 0x8000_0000;
+const DOMAINCFG_IE: u32 = 1 << 8;
+const DOMAINCFG_DM: u32 = 1 << 2;
+
+// `sourcecfg` source modes.
+const SOURCE_MODE_MASK: u32 = 0b111;
+const SOURCE_MODE_INACTIVE: u32 = 0;
+const SOURCE_MODE_DETACHED: u32 = 1;
+
+// The fields of `target` and `genmsi` in MSI delivery mode. The guest index is always 0, the
+// guests only see a supervisor-level domain.
+const TARGET_HART_SHIFT: u32 = 18;
+const TARGET_EIID_MASK: u32 = 0x7ff;
+const GENMSI_BUSY: u32 = 1 << 12;
+
+const ID_WORDS: usize = IMSIC_MAX_IDS / 64;
+const SOURCE_WORDS: usize = APLIC_MAX_SOURCES / 32;
+
+/// An emulated IMSIC interrupt file, for a vCPU without a guest interrupt file. The guest
+/// accesses its registers through `sireg` and `stopei`, which trap, and signals MSIs through its
+/// MSI page, which is emulated by `MmioDevice`.
+#[derive(Clone)]
+pub struct ImsicFile {
+    num_ids: usize,
+    eidelivery: bool,
+    eithreshold: usize,
+    eip: [u64; ID_WORDS],
+    eie: [u64; ID_WORDS],
+}
+
+impl ImsicFile {
+    /// Creates an interrupt file implementing the identities below `num_ids`, rounded up to a
+    /// multiple of 64 and at most `IMSIC_MAX_IDS`.
+    pub fn new(num_ids: usize) -> Self {
+        Self {
+            num_ids: ((num_ids + 63) & !63).min(IMSIC_MAX_IDS),
+            eidelivery: false,
+            eithreshold: 0,
+            eip: [0; ID_WORDS],
+            eie: [0; ID_WORDS],
+        }
+    }
+
+    /// Sets identity `id` pending, as an MSI does. Unimplemented identities are ignored.
+    pub fn set_pending(&mut self, id: usize) {
+        if id != 0 && id < self.num_ids {
+            self.eip[id / 64] |= 1 << (id % 64);
+        }
+    }
+
+    /// Returns the pending and enabled identity of highest priority, i.e. the lowest one, if it
+    /// is below the threshold. Returns 0 if there's none.
+    pub fn top_id(&self) -> usize {
+        let words = self.num_ids / 64;
+        let id = (0..words)
+            .find(|&word| self.eip[word] & self.eie[word] != 0)
+            .map_or(0, |word| {
+                word * 64 + (self.eip[word] & self.eie[word]).trailing_zeros() as usize
+            });
+        if self.eithreshold != 0 && id >= self.eithreshold {
+            0
+        } else {
+            id
+        }
+    }
+
+    /// Returns whether the file asserts the external interrupt of its hart.
+    pub fn has_interrupt(&self) -> bool {
+        self.eidelivery && self.top_id() != 0
+    }
+
+    /// Returns `stopei`: the top identity in bits 16-26, and again as its priority in bits 0-10.
+    pub fn topei(&self) -> usize {
+        let id = self.top_id();
+        id << 16 | id
+    }
+
+    /// Clears the top identity, as a write to `stopei` does.
+    pub fn claim(&mut self) {
+        let id = self.top_id();
+        self.eip[id / 64] &= !(1 << (id % 64));
+    }
+
+    /// Reads the register selected by the `siselect` value `select`.
+    pub fn read_reg(&self, select: usize) -> HyperResult<usize> {
+        match select {
+            ISELECT_EIDELIVERY => Ok(self.eidelivery as usize),
+            ISELECT_EITHRESHOLD => Ok(self.eithreshold),
+            ISELECT_EIP0..=ISELECT_EIP63 => {
+                Ok(self.eip[self.word(select - ISELECT_EIP0)?] as usize)
+            }
+            ISELECT_EIE0..=ISELECT_EIE63 => {
+                Ok(self.eie[self.word(select - ISELECT_EIE0)?] as usize)
+            }
+            _ => Err(HyperError::InvalidInstruction),
+        }
+    }
+
+    /// Writes `val` to the register selected by the `siselect` value `select`.
+    pub fn write_reg(&mut self, select: usize, val: usize) -> HyperResult<()> {
+        match select {
+            ISELECT_EIDELIVERY => self.eidelivery = val & 1 != 0,
+            ISELECT_EITHRESHOLD => self.eithreshold = val & (IMSIC_MAX_IDS - 1),
+            ISELECT_EIP0..=ISELECT_EIP63 => {
+                let word = self.word(select - ISELECT_EIP0)?;
+                self.eip[word] = val as u64 & self.id_mask(word);
+            }
+            ISELECT_EIE0..=ISELECT_EIE63 => {
+                let word = self.word(select - ISELECT_EIE0)?;
+                self.eie[word] = val as u64 & self.id_mask(word);
+            }
+            _ => return Err(HyperError::InvalidInstruction),
+        }
+        Ok(())
+    }
+
+    /// Returns the word of the `eip`/`eie` arrays at register `index` of the array. Only the
+    /// even registers exist on RV64, each covering 64 identities.
+    fn word(&self, index: usize) -> HyperResult<usize> {
+        if index % 2 != 0 {
+            return Err(HyperError::InvalidInstruction);
+        }
+        Ok(index / 2)
+    }
+
+    /// Returns the mask of implemented identities in word `word` of the `eip`/`eie` arrays.
+    fn id_mask(&self, word: usize) -> u64 {
+        match word {
+            0 => !1,
+            word if word < self.num_ids / 64 => !0,
+            _ => 0,
+        }
+    }
+}
+
+impl MmioDevice for ImsicFile {
+    fn read(&mut self, _offset: usize, width: usize) -> HyperResult<usize> {
+        if width != 4 {
+            return Err(HyperError::InvalidParam);
+        }
+        // The MSI page is write-only.
+        Ok(0)
+    }
+
+    fn write(&mut self, offset: usize, width: usize, val: usize) -> HyperResult<()> {
+        if width != 4 {
+            return Err(HyperError::InvalidParam);
+        }
+        match offset {
+            SETEIPNUM_LE => self.set_pending(val as u32 as usize),
+            SETEIPNUM_BE => self.set_pending((val as u32).swap_bytes() as usize),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// An MSI sent by the APLIC: the identity `eiid` for the interrupt file of hart `hart`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AplicMsi {
+    /// The hart index, which is the vCPU id.
+    pub hart: usize,
+    /// The interrupt identity.
+    pub eiid: usize,
+}
+
+/// The state of an APLIC domain in MSI delivery mode.
+pub struct AplicState {
+    num_sources: usize,
+    ie: bool,
+    sourcecfg: [u32; APLIC_MAX_SOURCES],
+    pending: [u32; SOURCE_WORDS],
+    enable: [u32; SOURCE_WORDS],
+    target: [u32; APLIC_MAX_SOURCES],
+    // MSIs to send once the registers are updated.
+    msis: Vec<AplicMsi>,
+}
+
+impl AplicState {
+    /// Creates an APLIC with `num_sources` sources, including the reserved source 0, all inactive.
+    pub fn new(num_sources: usize) -> Self {
+        Self {
+            num_sources: num_sources.min(APLIC_MAX_SOURCES),
+            ie: false,
+            sourcecfg: [SOURCE_MODE_INACTIVE; APLIC_MAX_SOURCES],
+            pending: [0; SOURCE_WORDS],
+            enable: [0; SOURCE_WORDS],
+            target: [0; APLIC_MAX_SOURCES],
+            msis: Vec::new(),
+        }
+    }
+
+    /// Sets `source` pending, as an edge on its input does. Inactive and detached sources ignore
+    /// their input.
+    pub fn trigger(&mut self, source: usize) {
+        if self.is_active(source) && self.sourcecfg[source] != SOURCE_MODE_DETACHED {
+            self.pending[source / 32] |= 1 << (source % 32);
+        }
+    }
+
+    /// Returns whether `source` is pending.
+    pub fn is_pending(&self, source: usize) -> bool {
+        source < self.num_sources && self.pending[source / 32] & (1 << (source % 32)) != 0
+    }
+
+    /// Sends the MSIs of the pending and enabled sources, which stop being pending, and the MSIs
+    /// generated through `genmsi`.
+    pub fn take_msis(&mut self) -> Vec<AplicMsi> {
+        if self.ie {
+            for word in 0..SOURCE_WORDS {
+                let mut ready = self.pending[word] & self.enable[word];
+                self.pending[word] &= !ready;
+                while ready != 0 {
+                    let source = word * 32 + ready.trailing_zeros() as usize;
+                    let msi = Self::msi(self.target[source]);
+                    self.msis.push(msi);
+                    ready &= ready - 1;
+                }
+            }
+        }
+        core::mem::take(&mut self.msis)
+    }
+
+    /// Reads the 32-bit register at `offset`.
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        if offset == DOMAINCFG {
+            DOMAINCFG_FIXED | DOMAINCFG_DM | if self.ie { DOMAINCFG_IE } else { 0 }
+        } else if let Some(word) = Self::word_reg(offset, SETIP_BASE) {
+            self.pending[word]
+        } else if let Some(word) = Self::word_reg(offset, SETIE_BASE) {
+            self.enable[word]
+        } else if let Some(source) = self.source_reg(offset, SOURCECFG_BASE) {
+            self.sourcecfg[source]
+        } else if let Some(source) = self.source_reg(offset, TARGET_BASE) {
+            self.target[source]
+        } else {
+            // Including the rectified inputs in `in_clrip`, which aren't modeled.
+            0
+        }
+    }
+
+    /// Writes `val` to the 32-bit register at `offset`.
+    pub fn write_u32(&mut self, offset: usize, val: u32) {
+        match offset {
+            DOMAINCFG => self.ie = val & DOMAINCFG_IE != 0,
+            SETIPNUM | SETIPNUM_LE => self.set_pending(val as usize, true),
+            SETIPNUM_BE => self.set_pending(val.swap_bytes() as usize, true),
+            CLRIPNUM => self.set_pending(val as usize, false),
+            SETIENUM => self.set_enable(val as usize, true),
+            CLRIENUM => self.set_enable(val as usize, false),
+            GENMSI => self.msis.push(Self::msi(val & !GENMSI_BUSY)),
+            _ => {
+                if let Some(word) = Self::word_reg(offset, SETIP_BASE) {
+                    self.pending[word] |= val & self.active_mask(word);
+                } else if let Some(word) = Self::word_reg(offset, IN_CLRIP_BASE) {
+                    self.pending[word] &= !val;
+                } else if let Some(word) = Self::word_reg(offset, SETIE_BASE) {
+                    self.enable[word] |= val & self.active_mask(word);
+                } else if let Some(word) = Self::word_reg(offset, CLRIE_BASE) {
+                    self.enable[word] &= !val;
+                } else if let Some(source) = self.source_reg(offset, SOURCECFG_BASE) {
+                    // No child domains to delegate to, and reserved modes are inactive.
+                    let mode = match val & SOURCE_MODE_MASK {
+                        2 | 3 => SOURCE_MODE_INACTIVE,
+                        mode => mode,
+                    };
+                    self.sourcecfg[source] = mode;
+                    if mode == SOURCE_MODE_INACTIVE {
+                        self.pending[source / 32] &= !(1 << (source % 32));
+                        self.enable[source / 32] &= !(1 << (source % 32));
+                        self.target[source] = 0;
+                    }
+                } else if let Some(source) = self.source_reg(offset, TARGET_BASE) {
+                    if self.is_active(source) {
+                        self.target[source] = val & (!0 << TARGET_HART_SHIFT | TARGET_EIID_MASK);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the word of the per-word register at `offset` in the array starting at `base`.
+    fn word_reg(offset: usize, base: usize) -> Option<usize> {
+        let word = offset.checked_sub(base)? / 4;
+        (offset % 4 == 0 && word < SOURCE_WORDS).then_some(word)
+    }
+
+    /// Returns the source of the per-source register at `offset` in the array starting at
+    /// `base` with source 1, if it's implemented.
+    fn source_reg(&self, offset: usize, base: usize) -> Option<usize> {
+        let source = offset.checked_sub(base)? / 4 + 1;
+        (offset % 4 == 0 && source < self.num_sources).then_some(source)
+    }
+
+    fn is_active(&self, source: usize) -> bool {
+        source != 0 && source < self.num_sources && self.sourcecfg[source] != SOURCE_MODE_INACTIVE
+    }
+
+    /// Returns the mask of active sources in word `word` of the pending and enable bitmaps.
+    fn active_mask(&self, word: usize) -> u32 {
+        (0..32)
+            .filter(|bit| self.is_active(word * 32 + bit))
+            .fold(0, |mask, bit| mask | 1 << bit)
+    }
+
+    fn set_pending(&mut self, source: usize, pending: bool) {
+        if self.is_active(source) {
+            if pending {
+                self.pending[source / 32] |= 1 << (source % 32);
+            } else {
+                self.pending[source / 32] &= !(1 << (source % 32));
+            }
+        }
+    }
+
+    fn set_enable(&mut self, source: usize, enable: bool) {
+        if self.is_active(source) {
+            if enable {
+                self.enable[source / 32] |= 1 << (source % 32);
+            } else {
+                self.enable[source / 32] &= !(1 << (source % 32));
+            }
+        }
+    }
+
+    /// Decodes the MSI described by a `target` or `genmsi` value.
+    fn msi(target: u32) -> AplicMsi {
+        AplicMsi {
+            hart: (target >> TARGET_HART_SHIFT) as usize,
+            eiid: (target & TARGET_EIID_MASK) as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sourcecfg(source: usize) -> usize {
+        SOURCECFG_BASE + 4 * (source - 1)
+    }
+
+    fn target(source: usize) -> usize {
+        TARGET_BASE + 4 * (source - 1)
+    }
+
+    #[test]
+    fn imsic_top_id_respects_enable_and_threshold() {
+        let mut file = ImsicFile::new(256);
+        file.set_pending(70);
+        file.set_pending(5);
+        file.set_pending(300);
+        assert_eq!(file.top_id(), 0);
+        file.write_reg(ISELECT_EIE0 + 2, 1 << 6).unwrap();
+        assert_eq!(file.topei(), 70 << 16 | 70);
+        assert!(!file.has_interrupt());
+        file.write_reg(ISELECT_EIDELIVERY, 1).unwrap();
+        assert!(file.has_interrupt());
+        file.write_reg(ISELECT_EITHRESHOLD, 70).unwrap();
+        assert_eq!(file.top_id(), 0);
+        file.write_reg(ISELECT_EITHRESHOLD, 0).unwrap();
+        file.write_reg(ISELECT_EIE0, !0).unwrap();
+        assert_eq!(file.read_reg(ISELECT_EIE0).unwrap(), !1);
+        assert_eq!(file.top_id(), 5);
+        file.claim();
+        assert_eq!(file.top_id(), 70);
+        assert_eq!(file.read_reg(ISELECT_EIP0).unwrap(), 0);
+    }
+
+    #[test]
+    fn imsic_registers() {
+        let mut file = ImsicFile::new(64);
+        assert!(file.read_reg(ISELECT_EIP0 + 1).is_err());
+        assert!(file.write_reg(0x71, 0).is_err());
+        file.write_reg(ISELECT_EIP0 + 2, !0).unwrap();
+        assert_eq!(file.read_reg(ISELECT_EIP0 + 2).unwrap(), 0);
+        file.write(SETEIPNUM_BE, 4, (9u32).swap_bytes() as usize)
+            .unwrap();
+        assert_eq!(file.read_reg(ISELECT_EIP0).unwrap(), 1 << 9);
+    }
+
+    #[test]
+    fn aplic_sends_msis() {
+        let mut aplic = AplicState::new(32);
+        aplic.write_u32(sourcecfg(3), 4);
+        aplic.write_u32(target(3), 2 << TARGET_HART_SHIFT | 0x42);
+        aplic.write_u32(SETIENUM, 3);
+        aplic.trigger(3);
+        // Nothing is sent until the domain is enabled.
+        assert!(aplic.take_msis().is_empty());
+        assert!(aplic.is_pending(3));
+        aplic.write_u32(DOMAINCFG, DOMAINCFG_IE);
+        assert_eq!(
+            aplic.take_msis(),
+            [AplicMsi {
+                hart: 2,
+                eiid: 0x42
+            }]
+        );
+        assert!(!aplic.is_pending(3));
+        assert_eq!(
+            aplic.read_u32(DOMAINCFG),
+            DOMAINCFG_FIXED | DOMAINCFG_IE | DOMAINCFG_DM
+        );
+    }
+
+    #[test]
+    fn aplic_ignores_inactive_sources() {
+        let mut aplic = AplicState::new(32);
+        aplic.write_u32(DOMAINCFG, DOMAINCFG_IE);
+        aplic.write_u32(SETIE_BASE, !0);
+        aplic.write_u32(SETIP_BASE, !0);
+        aplic.trigger(5);
+        assert_eq!(aplic.read_u32(SETIP_BASE), 0);
+        assert!(aplic.take_msis().is_empty());
+        aplic.write_u32(sourcecfg(5), SOURCE_MODE_DETACHED);
+        aplic.trigger(5);
+        assert!(!aplic.is_pending(5));
+        aplic.write_u32(SETIPNUM_LE, 5);
+        assert!(aplic.is_pending(5));
+        aplic.write_u32(sourcecfg(40), 4);
+        assert_eq!(aplic.read_u32(sourcecfg(40)), 0);
+    }
+}
@@ -0,0 +1,288 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::ops::Range;
+
+use crate::{GuestPhysAddr, HyperError, HyperResult};
+
+/// An emulated device accessed by the guest through MMIO.
+pub trait MmioDevice {
+    /// Emulates a guest load of `width` bytes from the register at `offset`.
+    fn read(&mut self, offset: usize, width: usize) -> HyperResult<usize>;
+
+    /// Emulates a guest store of `width` bytes of `val` to the register at `offset`.
+    fn write(&mut self, offset: usize, width: usize, val: usize) -> HyperResult<()>;
+}
+
+/// Maps guest physical ranges to the emulated devices behind them.
+#[derive(Default)]
+pub struct MmioBus {
+    devices: Vec<(Range<GuestPhysAddr>, Box<dyn MmioDevice>)>,
+}
+
+impl MmioBus {
+    /// Creates an empty bus.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches `device` to the guest physical range `[base, base + size)`. Fails with
+    /// `InvalidParam` if the range is empty or overlaps an existing device.
+    pub fn register(
+        &mut self,
+        base: GuestPhysAddr,
+        size: usize,
+        device: Box<dyn MmioDevice>,
+    ) -> HyperResult<()> {
+        let end = base.checked_add(size).ok_or(HyperError::InvalidParam)?;
+        if size == 0
+            || self
+                .devices
+                .iter()
+                .any(|(range, _)| base < range.end && range.start < end)
+        {
+            return Err(HyperError::InvalidParam);
+        }
+        self.devices.push((base..end, device));
+        Ok(())
+    }
+
+    /// Detaches and returns the device at `base`.
+    pub fn unregister(&mut self, base: GuestPhysAddr) -> HyperResult<Box<dyn MmioDevice>> {
+        let index = self
+            .devices
+            .iter()
+            .position(|(range, _)| range.start == base)
+            .ok_or(HyperError::NotFound)?;
+        Ok(self.devices.swap_remove(index).1)
+    }
+
+    /// Returns whether a device is attached at `addr`.
+    pub fn contains(&self, addr: GuestPhysAddr) -> bool {
+        self.devices.iter().any(|(range, _)| range.contains(&addr))
+    }
+
+    /// Returns the device attached at `addr` and the offset of `addr` within its range.
+    pub fn find_mut(&mut self, addr: GuestPhysAddr) -> Option<(usize, &mut dyn MmioDevice)> {
+        let (range, device) = self
+            .devices
+            .iter_mut()
+            .find(|(range, _)| range.contains(&addr))?;
+        Some((addr - range.start, device.as_mut()))
+    }
+}
+
+/// A guest load or store to an emulated MMIO register, decoded from the faulting instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MmioAccess {
+    /// Whether the access is a store.
+    pub is_write: bool,
+    /// The access width in bytes.
+    pub width: usize,
+    /// Whether a loaded value is sign-extended to XLEN.
+    pub sign_extend: bool,
+    /// The number of the destination register of a load or the source register of a store, from
+    /// 0 to 31.
+    pub reg: u32,
+    /// The length of the faulting instruction in bytes.
+    pub len: usize,
+}
+
+impl MmioAccess {
+    /// Decodes the raw instruction `inst` fetched from guest memory. Only the low 16 bits are
+    /// looked at for a compressed instruction.
+    pub fn decode(inst: u32) -> HyperResult<Self> {
+        if inst & 0b11 == 0b11 {
+            Self::decode_standard(inst, 4)
+        } else {
+            Self::decode_compressed(inst as u16)
+        }
+    }
+
+    /// Decodes a transformed instruction reported in `htinst`. Bit 1 tells whether the trapping
+    /// instruction was a 32-bit (set) or a compressed (clear) one; the rest is the equivalent
+    /// standard load or store with its address offset and `rs1` zeroed. Pseudo-instructions,
+    /// reported for implicit accesses of the VS-stage page table walk, are rejected.
+    pub fn decode_transformed(htinst: u32) -> HyperResult<Self> {
+        if htinst & 0b1 == 0 {
+            return Err(HyperError::InvalidInstruction);
+        }
+        let len = if htinst & 0b10 != 0 { 4 } else { 2 };
+        Self::decode_standard(htinst | 0b10, len)
+    }
+
+    fn decode_standard(inst: u32, len: usize) -> HyperResult<Self> {
+        let funct3 = (inst >> 12) & 0b111;
+        let (is_write, reg, width, sign_extend) = match inst & 0x7f {
+            OPCODE_LOAD => {
+                let (width, sign_extend) = match funct3 {
+                    0b000 => (1, true),
+                    0b001 => (2, true),
+                    0b010 => (4, true),
+                    0b011 => (8, false),
+                    0b100 => (1, false),
+                    0b101 => (2, false),
+                    0b110 => (4, false),
+                    _ => return Err(HyperError::InvalidInstruction),
+                };
+                (false, (inst >> 7) & 0x1f, width, sign_extend)
+            }
+            OPCODE_STORE => {
+                if funct3 > 0b011 {
+                    return Err(HyperError::InvalidInstruction);
+                }
+                (true, (inst >> 20) & 0x1f, 1 << funct3, false)
+            }
+            _ => return Err(HyperError::InvalidInstruction),
+        };
+        Ok(Self {
+            is_write,
+            width,
+            sign_extend,
+            reg,
+            len,
+        })
+    }
+
+    fn decode_compressed(inst: u16) -> HyperResult<Self> {
+        let inst = inst as u32;
+        let funct3 = (inst >> 13) & 0b111;
+        // The 3-bit rd'/rs2' field of the CL/CS formats, which maps to x8-x15.
+        let reg_prime = ((inst >> 2) & 0b111) + 8;
+        let (is_write, reg, width) = match (inst & 0b11, funct3) {
+            // C.LW, C.LD, C.SW, C.SD
+            (0b00, 0b010) => (false, reg_prime, 4),
+            (0b00, 0b011) => (false, reg_prime, 8),
+            (0b00, 0b110) => (true, reg_prime, 4),
+            (0b00, 0b111) => (true, reg_prime, 8),
+            // C.LWSP, C.LDSP, C.SWSP, C.SDSP
+            (0b10, 0b010) => (false, (inst >> 7) & 0x1f, 4),
+            (0b10, 0b011) => (false, (inst >> 7) & 0x1f, 8),
+            (0b10, 0b110) => (true, (inst >> 2) & 0x1f, 4),
+            (0b10, 0b111) => (true, (inst >> 2) & 0x1f, 8),
+            _ => return Err(HyperError::InvalidInstruction),
+        };
+        Ok(Self {
+            is_write,
+            width,
+            sign_extend: width == 4,
+            reg,
+            len: 2,
+        })
+    }
+
+    /// Performs the access on the register at `offset` of `device`. `val` is the value of
+    /// register `reg`: a store writes it to the device, a load replaces it with the loaded value.
+    pub fn emulate(
+        &self,
+        device: &mut dyn MmioDevice,
+        offset: usize,
+        val: &mut usize,
+    ) -> HyperResult<()> {
+        let bits = self.width * 8;
+        if self.is_write {
+            let mut val = *val;
+            if bits < usize::BITS as usize {
+                val &= (1 << bits) - 1;
+            }
+            device.write(offset, self.width, val)
+        } else {
+            let mut loaded = device.read(offset, self.width)?;
+            if bits < usize::BITS as usize {
+                let shift = usize::BITS as usize - bits;
+                loaded = if self.sign_extend {
+                    (((loaded << shift) as isize) >> shift) as usize
+                } else {
+                    (loaded << shift) >> shift
+                };
+            }
+            *val = loaded;
+            Ok(())
+        }
+    }
+}
+
+const OPCODE_LOAD: u32 = 0b000_0011;
+const OPCODE_STORE: u32 = 0b010_0011;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_standard_loads_and_stores() {
+        // lbu a0, 0(a1)
+        let access = MmioAccess::decode(0x0005_c503).unwrap();
+        assert!(!access.is_write && !access.sign_extend);
+        assert_eq!((access.width, access.reg, access.len), (1, 10, 4));
+        // lh t0, 2(a0)
+        let access = MmioAccess::decode(0x0025_1283).unwrap();
+        assert!(!access.is_write && access.sign_extend);
+        assert_eq!((access.width, access.reg), (2, 5));
+        // sd a2, 8(a0)
+        let access = MmioAccess::decode(0x00c5_3423).unwrap();
+        assert!(access.is_write);
+        assert_eq!((access.width, access.reg), (8, 12));
+        // addi a0, a0, 1
+        assert!(MmioAccess::decode(0x0015_0513).is_err());
+    }
+
+    #[test]
+    fn decode_compressed_loads_and_stores() {
+        // c.lw a0, 0(a1)
+        let access = MmioAccess::decode(0x4188).unwrap();
+        assert!(!access.is_write && access.sign_extend);
+        assert_eq!((access.width, access.reg, access.len), (4, 10, 2));
+        // c.sd a5, 8(a0)
+        let access = MmioAccess::decode(0xe51c).unwrap();
+        assert!(access.is_write);
+        assert_eq!((access.width, access.reg), (8, 15));
+        // c.lwsp a3, 4(sp)
+        let access = MmioAccess::decode(0x4692).unwrap();
+        assert_eq!((access.width, access.reg), (4, 13));
+    }
+
+    #[test]
+    fn decode_transformed_instructions() {
+        // Transformed sb a1, 0(x0) of a 32-bit instruction.
+        let access = MmioAccess::decode_transformed(0x00b0_0023).unwrap();
+        assert!(access.is_write);
+        assert_eq!((access.width, access.reg, access.len), (1, 11, 4));
+        // Transformed lw a0, 0(x0) of a compressed c.lw.
+        let access = MmioAccess::decode_transformed(0x0000_2501).unwrap();
+        assert_eq!((access.width, access.reg, access.len), (4, 10, 2));
+        // Pseudo-instruction for a 64-bit VS-stage page table read.
+        assert!(MmioAccess::decode_transformed(0x0000_3000).is_err());
+    }
+
+    struct Register(usize);
+
+    impl MmioDevice for Register {
+        fn read(&mut self, _offset: usize, _width: usize) -> HyperResult<usize> {
+            Ok(self.0)
+        }
+
+        fn write(&mut self, _offset: usize, _width: usize, val: usize) -> HyperResult<()> {
+            self.0 = val;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn emulate_extends_loads_and_truncates_stores() {
+        let mut device = Register(0x1234_80f0);
+        let mut val = 0;
+        // lh t0, 2(a0)
+        let lh = MmioAccess::decode(0x0025_1283).unwrap();
+        lh.emulate(&mut device, 0, &mut val).unwrap();
+        assert_eq!(val, 0xffff_ffff_ffff_80f0);
+        // lbu a0, 0(a1)
+        let lbu = MmioAccess::decode(0x0005_c503).unwrap();
+        lbu.emulate(&mut device, 0, &mut val).unwrap();
+        assert_eq!(val, 0xf0);
+        // c.sw a0, 0(a1)
+        let sw = MmioAccess::decode(0xc188).unwrap();
+        let mut val = 0x1_2345_6789;
+        sw.emulate(&mut device, 0, &mut val).unwrap();
+        assert_eq!(device.0, 0x2345_6789);
+        assert_eq!(val, 0x1_2345_6789);
+    }
+}
@@ -0,0 +1,9 @@
+//! Emulated devices whose state machines don't depend on the architecture they're used on. The
+//! glue to the host's hardware lives in the devices module of the architecture.
+pub mod aia;
+pub mod mmio;
+pub mod plic;
+
+pub use aia::{AplicMsi, AplicState, ImsicFile};
+pub use mmio::{MmioAccess, MmioBus, MmioDevice};
+pub use plic::{PlicRegister, PlicSources, PlicState};
@@ -0,0 +1,543 @@
+use crate::vcpus::MAX_CPUS;
+
+/// Number of contexts for the PLIC. Value is twice the max number of harts because each hart will
+/// have one M-mode context and one S-mode context.
+pub const MAX_CONTEXTS: usize = 2 * MAX_CPUS;
+/// Number of interrupt sources for the PLIC. Source 0 is reserved and never raised.
+pub const MAX_SOURCES: usize = 1024;
+/// Size of the PLIC register space.
+pub const PLIC_SIZE: usize = 0x400_0000;
+
+// Register windows, as offsets from the PLIC base.
+const PRIORITY_BASE: usize = 0x0;
+const PENDING_BASE: usize = 0x1000;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const CONTEXT_THRESHOLD: usize = 0x0;
+const CONTEXT_CLAIM: usize = 0x4;
+
+/// Number of 32-bit words of the per-source bitmaps.
+pub const SOURCE_WORDS: usize = MAX_SOURCES / 32;
+
+/// The register windows of the PLIC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlicRegister {
+    /// Priority of the given source.
+    Priority(usize),
+    /// Pending bits of sources `32 * word..32 * word + 32`.
+    Pending(usize),
+    /// Enable bits of sources `32 * word..32 * word + 32` for `context`.
+    Enable {
+        /// The context.
+        context: usize,
+        /// The word in the enable bitmap.
+        word: usize,
+    },
+    /// Priority threshold of the given context.
+    Threshold(usize),
+    /// Claim/complete register of the given context.
+    ClaimComplete(usize),
+}
+
+impl PlicRegister {
+    /// Decodes the register at `offset` from the PLIC base, or `None` for reserved space.
+    pub fn from_offset(offset: usize) -> Option<Self> {
+        if offset & 0x3 != 0 {
+            return None;
+        }
+        if offset < PENDING_BASE {
+            let source = (offset - PRIORITY_BASE) / 4;
+            (source < MAX_SOURCES).then_some(Self::Priority(source))
+        } else if offset < ENABLE_BASE {
+            let word = (offset - PENDING_BASE) / 4;
+            (word < SOURCE_WORDS).then_some(Self::Pending(word))
+        } else if offset < CONTEXT_BASE {
+            let context = (offset - ENABLE_BASE) / ENABLE_STRIDE;
+            let word = (offset - ENABLE_BASE) % ENABLE_STRIDE / 4;
+            (context < MAX_CONTEXTS).then_some(Self::Enable { context, word })
+        } else if offset < PLIC_SIZE {
+            let context = (offset - CONTEXT_BASE) / CONTEXT_STRIDE;
+            if context >= MAX_CONTEXTS {
+                return None;
+            }
+            match (offset - CONTEXT_BASE) % CONTEXT_STRIDE {
+                CONTEXT_THRESHOLD => Some(Self::Threshold(context)),
+                CONTEXT_CLAIM => Some(Self::ClaimComplete(context)),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns the offset of the register from the PLIC base.
+    pub fn offset(self) -> usize {
+        match self {
+            Self::Priority(source) => PRIORITY_BASE + 4 * source,
+            Self::Pending(word) => PENDING_BASE + 4 * word,
+            Self::Enable { context, word } => ENABLE_BASE + ENABLE_STRIDE * context + 4 * word,
+            Self::Threshold(context) => CONTEXT_BASE + CONTEXT_STRIDE * context + CONTEXT_THRESHOLD,
+            Self::ClaimComplete(context) => CONTEXT_BASE + CONTEXT_STRIDE * context + CONTEXT_CLAIM,
+        }
+    }
+}
+
+/// Returns the S-mode PLIC context of the given hart.
+pub const fn supervisor_context(hart_id: usize) -> usize {
+    2 * hart_id + 1
+}
+
+/// Returns the hart of the given PLIC context if it is an S-mode context.
+pub const fn context_hart(context: usize) -> Option<usize> {
+    if context % 2 == 1 {
+        Some(context / 2)
+    } else {
+        None
+    }
+}
+
+/// A set of PLIC sources, e.g. the sources of the host PLIC passed through to a guest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlicSources {
+    bits: [u32; SOURCE_WORDS],
+}
+
+impl PlicSources {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; SOURCE_WORDS],
+        }
+    }
+
+    /// Adds `irq` to the set. Source 0 and out of range sources are ignored.
+    pub fn insert(&mut self, irq: u32) {
+        let irq = irq as usize;
+        if irq != 0 && irq < MAX_SOURCES {
+            self.bits[irq / 32] |= 1 << (irq % 32);
+        }
+    }
+
+    /// Returns whether `irq` is in the set.
+    pub fn contains(&self, irq: u32) -> bool {
+        let irq = irq as usize;
+        irq < MAX_SOURCES && self.bits[irq / 32] & (1 << (irq % 32)) != 0
+    }
+
+    /// Returns the sources of the set among `32 * word..32 * word + 32`.
+    pub fn word(&self, word: usize) -> u32 {
+        self.bits[word]
+    }
+
+    /// Returns the enable word `word` of a context shared with others: the bits of the sources
+    /// in the set are taken from `ours`, the others are kept from `current`.
+    pub fn merge_enable_word(&self, word: usize, current: u32, ours: u32) -> u32 {
+        current & !self.bits[word] | ours & self.bits[word]
+    }
+}
+
+impl Default for PlicSources {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<u32> for PlicSources {
+    fn from_iter<T: IntoIterator<Item = u32>>(iter: T) -> Self {
+        let mut sources = Self::new();
+        for irq in iter {
+            sources.insert(irq);
+        }
+        sources
+    }
+}
+
+/// A virtual PLIC. Interrupts are raised with `set_pending` and delivered to a context once they
+/// are enabled for it and their priority exceeds its threshold.
+pub struct PlicState {
+    base: usize,
+    num_sources: usize,
+    source_priority: [u32; MAX_SOURCES],
+    pending: [u32; SOURCE_WORDS],
+    enable: [[u32; SOURCE_WORDS]; MAX_CONTEXTS],
+    thresholds: [u32; MAX_CONTEXTS],
+    // Sources that have been claimed and not completed yet.
+    in_service: [u32; SOURCE_WORDS],
+}
+
+impl PlicState {
+    /// Creates a PLIC at `base` with `num_sources` interrupt sources, including the reserved
+    /// source 0.
+    pub fn new(base: usize, num_sources: usize) -> Self {
+        Self {
+            base,
+            num_sources: num_sources.min(MAX_SOURCES),
+            source_priority: [0; MAX_SOURCES],
+            pending: [0; SOURCE_WORDS],
+            enable: [[0; SOURCE_WORDS]; MAX_CONTEXTS],
+            thresholds: [0; MAX_CONTEXTS],
+            in_service: [0; SOURCE_WORDS],
+        }
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the number of interrupt sources, including the reserved source 0.
+    pub fn num_sources(&self) -> usize {
+        self.num_sources
+    }
+
+    /// Marks `irq` as pending. Out of range sources and source 0 are ignored.
+    pub fn set_pending(&mut self, irq: u32) {
+        let irq = irq as usize;
+        if irq != 0 && irq < self.num_sources {
+            self.pending[irq / 32] |= 1 << (irq % 32);
+        }
+    }
+
+    /// Returns whether `irq` is pending.
+    pub fn is_pending(&self, irq: u32) -> bool {
+        let irq = irq as usize;
+        irq < MAX_SOURCES && self.pending[irq / 32] & (1 << (irq % 32)) != 0
+    }
+
+    /// Returns whether `irq` is enabled for `context`.
+    pub fn is_enabled(&self, context: usize, irq: u32) -> bool {
+        let irq = irq as usize;
+        context < MAX_CONTEXTS
+            && irq < MAX_SOURCES
+            && self.enable[context][irq / 32] & (1 << (irq % 32)) != 0
+    }
+
+    /// Returns the enable bits of sources `32 * word..32 * word + 32` for `context`.
+    pub fn enable_word(&self, context: usize, word: usize) -> u32 {
+        self.enable[context][word]
+    }
+
+    /// Returns the sources among `32 * word..32 * word + 32` that are pending, or claimed and not
+    /// completed yet.
+    pub fn unfinished_word(&self, word: usize) -> u32 {
+        self.pending[word] | self.in_service[word]
+    }
+
+    /// Returns the pending source `context` should take next: the enabled source with the highest
+    /// priority above the context's threshold, preferring the lowest ID on ties.
+    pub fn highest_pending(&self, context: usize) -> Option<u32> {
+        if context >= MAX_CONTEXTS {
+            return None;
+        }
+        let mut best: Option<(u32, u32)> = None;
+        for word in 0..SOURCE_WORDS {
+            let mut candidates = self.pending[word] & self.enable[context][word];
+            while candidates != 0 {
+                let bit = candidates.trailing_zeros();
+                candidates &= !(1 << bit);
+                let irq = word as u32 * 32 + bit;
+                let priority = self.source_priority[irq as usize];
+                if priority <= self.thresholds[context] {
+                    continue;
+                }
+                if best.map_or(true, |(_, best_priority)| priority > best_priority) {
+                    best = Some((irq, priority));
+                }
+            }
+        }
+        best.map(|(irq, _)| irq)
+    }
+
+    /// Returns whether `context` has an interrupt to take, i.e. whether its external interrupt
+    /// line is asserted.
+    pub fn has_interrupt(&self, context: usize) -> bool {
+        self.highest_pending(context).is_some()
+    }
+
+    /// Claims the highest pending source for `context`, returning 0 if there is none.
+    pub fn claim(&mut self, context: usize) -> u32 {
+        let Some(irq) = self.highest_pending(context) else {
+            return 0;
+        };
+        let (word, bit) = (irq as usize / 32, irq % 32);
+        self.pending[word] &= !(1 << bit);
+        self.in_service[word] |= 1 << bit;
+        irq
+    }
+
+    /// Signals completion of `irq` by `context`. Returns whether the completion was accepted,
+    /// which requires `irq` to be in service and enabled for `context`.
+    pub fn complete(&mut self, context: usize, irq: u32) -> bool {
+        if irq == 0 || !self.is_enabled(context, irq) {
+            return false;
+        }
+        let (word, bit) = (irq as usize / 32, irq % 32);
+        if self.in_service[word] & (1 << bit) == 0 {
+            return false;
+        }
+        self.in_service[word] &= !(1 << bit);
+        true
+    }
+
+    /// Returns the mask of implemented sources in word `word` of the enable bitmap.
+    fn source_mask(&self, word: usize) -> u32 {
+        let first = word * 32;
+        let mut mask = match self.num_sources.saturating_sub(first) {
+            0 => 0,
+            n if n >= 32 => u32::MAX,
+            n => (1 << n) - 1,
+        };
+        if word == 0 {
+            mask &= !1;
+        }
+        mask
+    }
+
+    pub fn read_u32(&mut self, addr: usize) -> u32 {
+        let offset = addr.wrapping_sub(self.base);
+        match PlicRegister::from_offset(offset) {
+            Some(PlicRegister::Priority(source)) => self.source_priority[source],
+            Some(PlicRegister::Pending(word)) => self.pending[word],
+            Some(PlicRegister::Enable { context, word }) => self.enable[context][word],
+            Some(PlicRegister::Threshold(context)) => self.thresholds[context],
+            Some(PlicRegister::ClaimComplete(context)) => self.claim(context),
+            // Reserved space reads as zero.
+            None => 0,
+        }
+    }
+
+    /// Writes `val` to the register at `addr`. Returns the source completed by this write, if
+    /// any.
+    pub fn write_u32(&mut self, addr: usize, val: u32) -> Option<u32> {
+        let offset = addr.wrapping_sub(self.base);
+        match PlicRegister::from_offset(offset) {
+            // Source 0 does not exist, its priority is hardwired to zero.
+            Some(PlicRegister::Priority(source)) if source != 0 && source < self.num_sources => {
+                self.source_priority[source] = val
+            }
+            Some(PlicRegister::Enable { context, word }) => {
+                // Source 0 and sources beyond `num_sources` can never be enabled.
+                self.enable[context][word] = val & self.source_mask(word);
+            }
+            Some(PlicRegister::Threshold(context)) => self.thresholds[context] = val,
+            Some(PlicRegister::ClaimComplete(context)) => {
+                return self.complete(context, val).then_some(val);
+            }
+            // Pending bits are read-only, writes to them and to reserved space are ignored.
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: usize = 0xc00_0000;
+
+    fn priority(irq: usize) -> usize {
+        BASE + PRIORITY_BASE + 4 * irq
+    }
+
+    fn enable(context: usize, irq: usize) -> usize {
+        BASE + ENABLE_BASE + ENABLE_STRIDE * context + 4 * (irq / 32)
+    }
+
+    fn threshold(context: usize) -> usize {
+        BASE + CONTEXT_BASE + CONTEXT_STRIDE * context + CONTEXT_THRESHOLD
+    }
+
+    fn claim(context: usize) -> usize {
+        BASE + CONTEXT_BASE + CONTEXT_STRIDE * context + CONTEXT_CLAIM
+    }
+
+    fn enable_irq(plic: &mut PlicState, context: usize, irq: usize) {
+        let val = plic.read_u32(enable(context, irq)) | 1 << (irq % 32);
+        plic.write_u32(enable(context, irq), val);
+    }
+
+    #[test]
+    fn register_windows() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        plic.write_u32(priority(10), 5);
+        assert_eq!(plic.read_u32(priority(10)), 5);
+        plic.write_u32(enable(3, 40), 0xdead_beef);
+        assert_eq!(plic.read_u32(enable(3, 40)), 0xdead_beef);
+        assert_eq!(plic.read_u32(enable(2, 40)), 0);
+        plic.write_u32(threshold(3), 2);
+        assert_eq!(plic.read_u32(threshold(3)), 2);
+        // Reserved space reads as zero and ignores writes.
+        plic.write_u32(BASE + CONTEXT_BASE + 8, 1);
+        assert_eq!(plic.read_u32(BASE + CONTEXT_BASE + 8), 0);
+    }
+
+    #[test]
+    fn source_zero_is_reserved() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        plic.write_u32(priority(0), 7);
+        assert_eq!(plic.read_u32(priority(0)), 0);
+        plic.write_u32(enable(1, 0), u32::MAX);
+        assert_eq!(plic.read_u32(enable(1, 0)), !1);
+        plic.set_pending(0);
+        assert!(!plic.is_pending(0));
+    }
+
+    #[test]
+    fn pending_is_read_only() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        plic.set_pending(33);
+        assert_eq!(plic.read_u32(BASE + PENDING_BASE + 4), 1 << 1);
+        plic.write_u32(BASE + PENDING_BASE + 4, 0);
+        assert!(plic.is_pending(33));
+    }
+
+    #[test]
+    fn claim_respects_enable_and_threshold() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        plic.write_u32(priority(10), 1);
+        plic.set_pending(10);
+        // Not enabled for context 1 yet.
+        assert!(!plic.has_interrupt(1));
+        assert_eq!(plic.read_u32(claim(1)), 0);
+
+        enable_irq(&mut plic, 1, 10);
+        plic.write_u32(threshold(1), 1);
+        // Priority must be strictly greater than the threshold.
+        assert!(!plic.has_interrupt(1));
+        plic.write_u32(threshold(1), 0);
+        assert!(plic.has_interrupt(1));
+        // Other contexts are not affected.
+        assert!(!plic.has_interrupt(3));
+    }
+
+    #[test]
+    fn priority_zero_never_interrupts() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        enable_irq(&mut plic, 1, 4);
+        plic.set_pending(4);
+        assert!(!plic.has_interrupt(1));
+    }
+
+    #[test]
+    fn claim_highest_priority_lowest_id() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        for (irq, prio) in [(3, 2), (7, 5), (9, 5), (40, 1)] {
+            plic.write_u32(priority(irq), prio);
+            enable_irq(&mut plic, 1, irq);
+            plic.set_pending(irq as u32);
+        }
+        assert_eq!(plic.read_u32(claim(1)), 7);
+        assert_eq!(plic.read_u32(claim(1)), 9);
+        assert_eq!(plic.read_u32(claim(1)), 3);
+        assert_eq!(plic.read_u32(claim(1)), 40);
+        assert_eq!(plic.read_u32(claim(1)), 0);
+    }
+
+    #[test]
+    fn claim_and_complete() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        plic.write_u32(priority(5), 1);
+        enable_irq(&mut plic, 1, 5);
+        plic.set_pending(5);
+
+        assert_eq!(plic.read_u32(claim(1)), 5);
+        assert!(!plic.is_pending(5));
+        assert!(!plic.has_interrupt(1));
+
+        // Completing a source that is not in service is ignored.
+        assert_eq!(plic.write_u32(claim(1), 6), None);
+        // Completing from a context the source is not enabled for is ignored.
+        assert_eq!(plic.write_u32(claim(3), 5), None);
+        assert_eq!(plic.write_u32(claim(1), 5), Some(5));
+        // A second completion is ignored.
+        assert_eq!(plic.write_u32(claim(1), 5), None);
+    }
+
+    #[test]
+    fn contexts_arbitrate_independently() {
+        let mut plic = PlicState::new(BASE, MAX_SOURCES);
+        plic.write_u32(priority(1), 1);
+        plic.write_u32(priority(2), 3);
+        enable_irq(&mut plic, 1, 1);
+        enable_irq(&mut plic, 3, 1);
+        enable_irq(&mut plic, 3, 2);
+        plic.write_u32(threshold(3), 2);
+        plic.set_pending(1);
+        plic.set_pending(2);
+
+        assert_eq!(plic.highest_pending(1), Some(1));
+        assert_eq!(plic.highest_pending(3), Some(2));
+        // Claiming from one context clears the pending bit for all of them.
+        assert_eq!(plic.read_u32(claim(1)), 1);
+        assert_eq!(plic.highest_pending(3), Some(2));
+        assert_eq!(plic.read_u32(claim(3)), 2);
+        assert!(!plic.has_interrupt(1));
+    }
+
+    #[test]
+    fn unimplemented_sources() {
+        let mut plic = PlicState::new(BASE, 40);
+        plic.write_u32(priority(45), 1);
+        assert_eq!(plic.read_u32(priority(45)), 0);
+        plic.write_u32(enable(1, 32), u32::MAX);
+        assert_eq!(plic.read_u32(enable(1, 32)), 0xff);
+        plic.write_u32(enable(1, 64), u32::MAX);
+        assert_eq!(plic.read_u32(enable(1, 64)), 0);
+        plic.set_pending(40);
+        assert!(!plic.is_pending(40));
+    }
+
+    #[test]
+    fn sources_merge_enable_word() {
+        let sources: PlicSources = [0, 3, 33, 2000].into_iter().collect();
+        assert!(!sources.contains(0));
+        assert!(sources.contains(3) && sources.contains(33));
+        assert!(!sources.contains(4) && !sources.contains(2000));
+        assert_eq!(sources.word(0), 1 << 3);
+
+        // The host enabled sources 3 and 5 on the context, only 3 is the guest's: a guest write
+        // enabling 4 and disabling 3 leaves 5 enabled, and doesn't enable 4.
+        let host = 1 << 3 | 1 << 5;
+        assert_eq!(sources.merge_enable_word(0, host, 1 << 4), 1 << 5);
+        assert_eq!(
+            sources.merge_enable_word(0, 1 << 5, u32::MAX),
+            1 << 3 | 1 << 5
+        );
+        assert_eq!(sources.merge_enable_word(1, u32::MAX, 0), !(1 << 1));
+    }
+
+    #[test]
+    fn register_decode() {
+        assert_eq!(
+            PlicRegister::from_offset(0x4),
+            Some(PlicRegister::Priority(1))
+        );
+        assert_eq!(
+            PlicRegister::from_offset(0x1004),
+            Some(PlicRegister::Pending(1))
+        );
+        assert_eq!(
+            PlicRegister::from_offset(0x2084),
+            Some(PlicRegister::Enable {
+                context: 1,
+                word: 1
+            })
+        );
+        assert_eq!(
+            PlicRegister::from_offset(0x20_1000),
+            Some(PlicRegister::Threshold(1))
+        );
+        assert_eq!(
+            PlicRegister::from_offset(0x20_1004),
+            Some(PlicRegister::ClaimComplete(1))
+        );
+        assert_eq!(PlicRegister::from_offset(0x20_1008), None);
+        for offset in [0x4, 0x1004, 0x2084, 0x20_1000, 0x20_1004] {
+            assert_eq!(PlicRegister::from_offset(offset).unwrap().offset(), offset);
+        }
+        assert_eq!(PlicRegister::from_offset(0x2), None);
+        assert_eq!(PlicRegister::from_offset(PLIC_SIZE), None);
+    }
+}
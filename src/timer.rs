@@ -0,0 +1,182 @@
+//! vCPU timers emulated over the timer of the physical CPU they run on.
+
+/// The timer of a vCPU, for hosts whose CPUs can't run a timer for the guest. The deadline is in
+/// the host's time base, and stays with the vCPU: it follows the vCPU across physical CPUs, and
+/// the vCPUs of other VMs sharing a CPU have their own. A CPU's timer only ever holds the deadline
+/// of the vCPU running on it, loaded into the CPU's `CpuTimer` as the vCPU enters the guest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VCpuTimer {
+    deadline: Option<u64>,
+}
+
+impl VCpuTimer {
+    /// Creates a disarmed timer.
+    pub const fn new() -> Self {
+        Self { deadline: None }
+    }
+
+    /// Arms the timer to fire at `deadline`, replacing the previous deadline if any.
+    pub fn set(&mut self, deadline: u64) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Disarms the timer.
+    pub fn clear(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Returns the deadline the timer of the CPU the vCPU runs on must be programmed with.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Disarms the timer if its deadline is at or before `now`. Returns whether it did, i.e.
+    /// whether the vCPU's timer interrupt must be raised.
+    pub fn expire(&mut self, now: u64) -> bool {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {
+                self.deadline = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The timer of a physical CPU, multiplexed between the host's own deadline and the one of the
+/// vCPU running on the CPU. The CPU's timer is programmed with the earliest of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuTimer {
+    host: Option<u64>,
+    guest: Option<u64>,
+}
+
+impl CpuTimer {
+    /// Creates a timer with no deadline.
+    pub const fn new() -> Self {
+        Self {
+            host: None,
+            guest: None,
+        }
+    }
+
+    /// Sets the host's own deadline, `None` to disarm it.
+    pub fn set_host(&mut self, deadline: Option<u64>) {
+        self.host = deadline;
+    }
+
+    /// Returns the host's own deadline.
+    pub fn host_deadline(&self) -> Option<u64> {
+        self.host
+    }
+
+    /// Returns whether the host's deadline is at or before `now`, i.e. whether the CPU's timer
+    /// interrupt is, at least in part, the host's.
+    pub fn host_expired(&self, now: u64) -> bool {
+        self.host.map_or(false, |deadline| deadline <= now)
+    }
+
+    /// Takes on the deadline of `timer`, the timer of the vCPU running on this CPU, after
+    /// expiring it at `now`. Called as the vCPU is loaded and whenever its timer changes or may
+    /// have fired. Returns whether the vCPU's timer interrupt must be raised.
+    pub fn load(&mut self, timer: &mut VCpuTimer, now: u64) -> bool {
+        let fired = timer.expire(now);
+        self.guest = timer.deadline();
+        fired
+    }
+
+    /// Drops the deadline of the vCPU leaving this CPU, which stays with its `VCpuTimer`.
+    pub fn put(&mut self) {
+        self.guest = None;
+    }
+
+    /// Returns the deadline the CPU's timer must be programmed with, the earliest of the host's
+    /// and the running vCPU's.
+    pub fn deadline(&self) -> Option<u64> {
+        match (self.host, self.guest) {
+            (Some(host), Some(guest)) => Some(host.min(guest)),
+            (host, guest) => host.or(guest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expire_fires_once() {
+        let mut timer = VCpuTimer::new();
+        assert!(!timer.expire(u64::MAX));
+        timer.set(100);
+        timer.set(200);
+        assert!(!timer.expire(150));
+        assert_eq!(timer.deadline(), Some(200));
+        assert!(timer.expire(200));
+        assert_eq!(timer.deadline(), None);
+        assert!(!timer.expire(300));
+    }
+
+    #[test]
+    fn host_deadline_is_kept() {
+        let mut cpu = CpuTimer::new();
+        cpu.set_host(Some(500));
+        let mut vcpu = VCpuTimer::new();
+        vcpu.set(100);
+        assert!(!cpu.load(&mut vcpu, 50));
+        assert_eq!(cpu.deadline(), Some(100));
+        // The vCPU's timer fires first, the host's deadline is still programmed after it.
+        assert!(cpu.load(&mut vcpu, 100));
+        assert!(!cpu.host_expired(100));
+        assert_eq!(cpu.deadline(), Some(500));
+        vcpu.set(800);
+        assert!(!cpu.load(&mut vcpu, 200));
+        assert_eq!(cpu.deadline(), Some(500));
+        assert!(cpu.host_expired(500));
+        // Putting the vCPU leaves the host's deadline alone.
+        cpu.put();
+        assert_eq!(cpu.deadline(), Some(500));
+        cpu.set_host(None);
+        assert_eq!(cpu.deadline(), None);
+    }
+
+    #[test]
+    fn two_vms_share_a_cpu() {
+        let mut cpu = CpuTimer::new();
+        cpu.set_host(Some(1000));
+        // vCPU 0 of two VMs, taking turns on the same CPU.
+        let mut vm_a = VCpuTimer::new();
+        let mut vm_b = VCpuTimer::new();
+
+        vm_a.set(100);
+        assert!(!cpu.load(&mut vm_a, 50));
+        assert_eq!(cpu.deadline(), Some(100));
+        cpu.put();
+        vm_b.set(300);
+        assert!(!cpu.load(&mut vm_b, 60));
+        assert_eq!(cpu.deadline(), Some(300));
+        cpu.put();
+        // VM A's deadline survived VM B arming its timer, and fires once A runs again.
+        assert!(cpu.load(&mut vm_a, 150));
+        assert_eq!(cpu.deadline(), Some(1000));
+        cpu.put();
+
+        // A reset of VM B leaves VM A's timer armed.
+        vm_a.set(400);
+        vm_b.clear();
+        assert!(!cpu.load(&mut vm_b, 200));
+        assert_eq!(cpu.deadline(), Some(1000));
+        cpu.put();
+        assert!(!cpu.load(&mut vm_a, 200));
+        assert_eq!(cpu.deadline(), Some(400));
+        cpu.put();
+
+        // VM A's vCPU moves to another CPU, its deadline goes with it.
+        let mut other_cpu = CpuTimer::new();
+        assert!(!other_cpu.load(&mut vm_a, 250));
+        assert_eq!(other_cpu.deadline(), Some(400));
+        // VM B running on the first CPU again only has the host's deadline there.
+        assert!(!cpu.load(&mut vm_b, 260));
+        assert_eq!(cpu.deadline(), Some(1000));
+    }
+}
@@ -0,0 +1,264 @@
+//! The topology of the host's physical CPUs.
+use alloc::vec::Vec;
+
+use crate::{HyperError, HyperResult};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// The physical CPUs of the host, identified by their hardware ids: hart IDs on RISC-V, MPIDR
+/// affinity values on AArch64. The CPUs are numbered from 0 in the order of their hardware ids
+/// here, which gives the index of their per-CPU area.
+#[derive(Clone, Debug)]
+pub struct CpuTopology {
+    hw_ids: Vec<usize>,
+}
+
+// A CPU node of the device tree, while its properties are being read.
+struct CpuNode {
+    is_cpu: bool,
+    reg: Option<usize>,
+    okay: bool,
+}
+
+impl CpuTopology {
+    /// Creates the topology of the CPUs with the hardware ids `hw_ids`, given by the embedder.
+    pub fn from_hw_ids(hw_ids: &[usize]) -> HyperResult<Self> {
+        if hw_ids.is_empty() {
+            return Err(HyperError::InvalidParam);
+        }
+        for (i, hw_id) in hw_ids.iter().enumerate() {
+            if hw_ids[..i].contains(hw_id) {
+                return Err(HyperError::InvalidParam);
+            }
+        }
+        Ok(Self {
+            hw_ids: hw_ids.to_vec(),
+        })
+    }
+
+    /// Reads the topology from the `cpu` nodes under `/cpus` in the flattened device tree at
+    /// `fdt`. CPUs whose status isn't "okay" are left out.
+    ///
+    /// # Safety
+    ///
+    /// `fdt` must point to a device tree blob, mapped for its whole `totalsize`.
+    pub unsafe fn from_fdt(fdt: *const u8) -> HyperResult<Self> {
+        let header = |offset: usize| u32::from_be((fdt.add(offset) as *const u32).read_unaligned());
+        if header(0) != FDT_MAGIC {
+            return Err(HyperError::InvalidParam);
+        }
+        let blob = core::slice::from_raw_parts(fdt, header(4) as usize);
+        Self::parse_fdt(blob)
+    }
+
+    /// Returns the number of CPUs.
+    pub fn num_cpus(&self) -> usize {
+        self.hw_ids.len()
+    }
+
+    /// Returns the hardware id of CPU `cpu_id`.
+    pub fn hw_id(&self, cpu_id: usize) -> Option<usize> {
+        self.hw_ids.get(cpu_id).copied()
+    }
+
+    /// Returns the number of the CPU with hardware id `hw_id`.
+    pub fn cpu_id(&self, hw_id: usize) -> Option<usize> {
+        self.hw_ids.iter().position(|&id| id == hw_id)
+    }
+
+    fn parse_fdt(blob: &[u8]) -> HyperResult<Self> {
+        let be32 = |offset: usize| -> HyperResult<u32> {
+            blob.get(offset..offset + 4)
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+                .ok_or(HyperError::DecodeError)
+        };
+        let c_str = |offset: usize| -> HyperResult<&[u8]> {
+            let bytes = blob.get(offset..).ok_or(HyperError::DecodeError)?;
+            let len = bytes
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(HyperError::DecodeError)?;
+            Ok(&bytes[..len])
+        };
+        let align4 = |len: usize| (len + 3) & !3;
+
+        let strings = be32(12)? as usize;
+        let mut offset = be32(8)? as usize;
+        // The root node is at depth 1, `/cpus` at depth 2 and the CPUs at depth 3.
+        let mut depth = 0;
+        let mut in_cpus = false;
+        let mut address_cells = 2;
+        let mut cpu = None;
+        let mut hw_ids = Vec::new();
+        loop {
+            let token = be32(offset)?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(offset)?;
+                    offset += align4(name.len() + 1);
+                    depth += 1;
+                    if depth == 2 {
+                        in_cpus = name == b"cpus";
+                    } else if depth == 3 && in_cpus {
+                        cpu = Some(CpuNode {
+                            is_cpu: name.starts_with(b"cpu@"),
+                            reg: None,
+                            okay: true,
+                        });
+                    }
+                }
+                FDT_END_NODE => {
+                    if depth == 3 {
+                        if let Some(CpuNode {
+                            is_cpu: true,
+                            reg,
+                            okay: true,
+                        }) = cpu.take()
+                        {
+                            hw_ids.push(reg.ok_or(HyperError::DecodeError)?);
+                        }
+                    } else if depth == 2 {
+                        in_cpus = false;
+                    }
+                    depth -= 1;
+                }
+                FDT_PROP => {
+                    let len = be32(offset)? as usize;
+                    let name = c_str(strings + be32(offset + 4)? as usize)?;
+                    let value_offset = offset + 8;
+                    let value = blob
+                        .get(value_offset..value_offset + len)
+                        .ok_or(HyperError::DecodeError)?;
+                    offset = value_offset + align4(len);
+                    if depth == 2 && in_cpus && name == b"#address-cells" {
+                        address_cells = be32(value_offset)?;
+                    } else if let (3, Some(node)) = (depth, cpu.as_mut()) {
+                        match name {
+                            b"device_type" => node.is_cpu = value == b"cpu\0",
+                            b"status" => node.okay = value == b"okay\0" || value == b"ok\0",
+                            // Only the first address matters, the others are for threads.
+                            b"reg" => {
+                                node.reg = Some(match address_cells {
+                                    1 => be32(value_offset)? as usize,
+                                    2 => {
+                                        (be32(value_offset)? as usize) << 32
+                                            | be32(value_offset + 4)? as usize
+                                    }
+                                    _ => return Err(HyperError::DecodeError),
+                                });
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => return Err(HyperError::DecodeError),
+            }
+        }
+        Self::from_hw_ids(&hw_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a device tree blob with the given CPUs under `/cpus`, as (unit address, status).
+    fn build_fdt(address_cells: u32, cpus: &[(u32, &str)]) -> Vec<u8> {
+        let mut strings = Vec::new();
+        let mut structure = Vec::new();
+        let mut string_offset = |name: &str| {
+            let offset = strings.len() as u32;
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let cells_name = string_offset("#address-cells");
+        let type_name = string_offset("device_type");
+        let reg_name = string_offset("reg");
+        let status_name = string_offset("status");
+
+        let begin_node = |s: &mut Vec<u8>, name: &str| {
+            s.extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+            s.extend_from_slice(name.as_bytes());
+            s.push(0);
+            s.resize((s.len() + 3) & !3, 0);
+        };
+        let prop = |s: &mut Vec<u8>, name: u32, value: &[u8]| {
+            s.extend_from_slice(&FDT_PROP.to_be_bytes());
+            s.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            s.extend_from_slice(&name.to_be_bytes());
+            s.extend_from_slice(value);
+            s.resize((s.len() + 3) & !3, 0);
+        };
+        begin_node(&mut structure, "");
+        begin_node(&mut structure, "cpus");
+        prop(&mut structure, cells_name, &address_cells.to_be_bytes());
+        for &(id, status) in cpus {
+            begin_node(&mut structure, &format!("cpu@{:x}", id));
+            prop(&mut structure, type_name, b"cpu\0");
+            let reg = match address_cells {
+                1 => id.to_be_bytes().to_vec(),
+                _ => (id as u64).to_be_bytes().to_vec(),
+            };
+            prop(&mut structure, reg_name, &reg);
+            prop(
+                &mut structure,
+                status_name,
+                format!("{}\0", status).as_bytes(),
+            );
+            begin_node(&mut structure, "interrupt-controller");
+            prop(&mut structure, reg_name, &[0xff; 4]);
+            structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+            structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+        }
+        structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+        structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+        structure.extend_from_slice(&FDT_END.to_be_bytes());
+
+        let mut blob = vec![0u8; 40];
+        let struct_offset = blob.len() as u32;
+        blob.extend_from_slice(&structure);
+        let strings_offset = blob.len() as u32;
+        blob.extend_from_slice(&strings);
+        let total_size = blob.len() as u32;
+        blob[0..4].copy_from_slice(&FDT_MAGIC.to_be_bytes());
+        blob[4..8].copy_from_slice(&total_size.to_be_bytes());
+        blob[8..12].copy_from_slice(&struct_offset.to_be_bytes());
+        blob[12..16].copy_from_slice(&strings_offset.to_be_bytes());
+        blob
+    }
+
+    #[test]
+    fn fdt_cpus() {
+        let blob = build_fdt(1, &[(0, "okay"), (1, "disabled"), (3, "okay")]);
+        let topology = unsafe { CpuTopology::from_fdt(blob.as_ptr()) }.unwrap();
+        assert_eq!(topology.num_cpus(), 2);
+        assert_eq!(topology.hw_id(1), Some(3));
+        assert_eq!(topology.cpu_id(3), Some(1));
+        assert_eq!(topology.cpu_id(1), None);
+
+        let blob = build_fdt(2, &[(0x100, "okay"), (0x101, "okay")]);
+        let topology = unsafe { CpuTopology::from_fdt(blob.as_ptr()) }.unwrap();
+        assert_eq!(topology.hw_id(1), Some(0x101));
+    }
+
+    #[test]
+    fn duplicate_hw_ids() {
+        assert_eq!(
+            CpuTopology::from_hw_ids(&[0, 1, 0]).unwrap_err(),
+            HyperError::InvalidParam
+        );
+        assert_eq!(
+            CpuTopology::from_hw_ids(&[]).unwrap_err(),
+            HyperError::InvalidParam
+        );
+    }
+}